@@ -0,0 +1,96 @@
+//! Interpreter throughput, to compare before and after performance work.
+//! Run with `cargo bench`; `chip8 bench` measures a whole rom instead.
+
+use chip8::blocks::Engine;
+use chip8::quirks::QuirkPreset;
+use chip8::rom::{Platform, Rom, Settings};
+use chip8::timing::Timing;
+use chip8::{decode, run_cycles, run_instructions, tick_timers, Chip8, Instruction};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::path::Path;
+use std::str::FromStr;
+
+fn decoding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.bench_function("binary", |b| {
+        b.iter(|| {
+            for opcode in (0..=u16::MAX).step_by(257) {
+                let [high, low] = opcode.to_be_bytes();
+                black_box(decode(black_box(high), black_box(low)));
+            }
+        })
+    });
+    // how `decode` used to work
+    group.bench_function("hex string", |b| {
+        b.iter(|| {
+            for opcode in (0..=u16::MAX).step_by(257) {
+                let hex = format!("{:04X}", black_box(opcode));
+                black_box(Instruction::from_str(&hex).ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+/// With the VIP's display wait each frame ends at the sprite, so every
+/// iteration draws once.
+fn drawing(c: &mut Criterion) {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    // DRW V0, V1, 15; JP 0x200, with I at the font
+    chip8.load(&[0xD0, 0x1F, 0x12, 0x00]);
+    c.bench_function("DRW 15 rows", |b| {
+        b.iter(|| {
+            run_instructions(&mut chip8, 2);
+            tick_timers(&mut chip8);
+        })
+    });
+}
+
+fn frames(c: &mut Criterion) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("Airplane.ch8");
+    let rom = Rom::load(&path, None).unwrap();
+    let settings = rom.settings(Settings::default());
+    let mut group = c.benchmark_group("Airplane frame");
+    for (name, engine, timing) in [
+        ("interpreter", Engine::Interpreter, Timing::Ipf),
+        ("blocks", Engine::Blocks, Timing::Ipf),
+        ("vip timing", Engine::Interpreter, Timing::Vip),
+    ] {
+        let mut chip8 = Chip8::new(rom.platform, settings.quirks);
+        chip8.seed(0);
+        chip8.load(&rom.bytes);
+        chip8.set_engine(engine);
+        let frame = |chip8: &mut Chip8| {
+            match timing {
+                Timing::Ipf => run_instructions(chip8, settings.ipf),
+                Timing::Vip => run_cycles(chip8),
+            };
+            tick_timers(chip8);
+        };
+        // past the title screen
+        for _ in 0..120 {
+            frame(&mut chip8);
+        }
+        group.bench_function(name, |b| b.iter(|| frame(&mut chip8)));
+    }
+    group.finish();
+}
+
+/// Long straight runs, the best case for the block engine.
+fn engines(c: &mut Criterion) {
+    let mut program = [0x70, 0x01].repeat(31);
+    program.extend([0x12, 0x00]);
+    let mut group = c.benchmark_group("32 instruction loop");
+    for engine in [Engine::Interpreter, Engine::Blocks] {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        chip8.load(&program);
+        chip8.set_engine(engine);
+        group.bench_function(engine.to_string(), |b| {
+            b.iter(|| run_instructions(&mut chip8, 32))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decoding, drawing, frames, engines);
+criterion_main!(benches);
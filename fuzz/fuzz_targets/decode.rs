@@ -0,0 +1,19 @@
+//! Checks the binary decoder the interpreter uses against decoding the
+//! opcode's hex with `Instruction::from_str`.
+
+#![no_main]
+
+use chip8::{decode, Instruction};
+use libfuzzer_sys::fuzz_target;
+use std::str::FromStr;
+
+fuzz_target!(|data: [u8; 2]| {
+    let [high, low] = data;
+    let hex = format!("{:02X}{:02X}", high, low);
+    assert_eq!(
+        decode(high, low),
+        Instruction::from_str(&hex).ok(),
+        "{} decodes differently",
+        hex
+    );
+});
@@ -0,0 +1,82 @@
+//! Runs arbitrary programs and checks the machine never panics and stays
+//! consistent: the program counter inside memory and the stack no deeper
+//! than it has room for. The first byte picks the quirks, the platform, the
+//! engine and the timing, and with the block engine an interpreter runs the
+//! same program alongside and has to end every frame in the same state.
+
+#![no_main]
+
+use chip8::blocks::Engine;
+use chip8::quirks::QuirkPreset;
+use chip8::rom::Platform;
+use chip8::timing::Timing;
+use chip8::{run_cycles, run_instructions, state, tick_timers, Chip8};
+use libfuzzer_sys::fuzz_target;
+
+const PRESETS: [QuirkPreset; 3] = [QuirkPreset::Vip, QuirkPreset::Schip, QuirkPreset::XoChip];
+const FRAMES: usize = 600;
+/// Odd, so blocks get cut off in the middle.
+const IPF: u32 = 17;
+
+fn machine(platform: Platform, preset: QuirkPreset, engine: Engine, program: &[u8]) -> Chip8 {
+    let mut chip8 = Chip8::new(platform, preset.quirks());
+    chip8.seed(0);
+    chip8.load(&program[..program.len().min(platform.max_rom_size())]);
+    chip8.set_engine(engine);
+    chip8
+}
+
+fn run_frame(chip8: &mut Chip8, timing: Timing) {
+    match timing {
+        Timing::Ipf => run_instructions(chip8, IPF),
+        Timing::Vip => run_cycles(chip8),
+    };
+    tick_timers(chip8);
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&setup, program)) = data.split_first() else {
+        return;
+    };
+    let preset = PRESETS[(setup & 0x0F) as usize % PRESETS.len()];
+    let timing = if setup & 0x20 == 0 {
+        Timing::Ipf
+    } else {
+        Timing::Vip
+    };
+    let engine = if setup & 0x40 == 0 {
+        Engine::Interpreter
+    } else {
+        Engine::Blocks
+    };
+    let platform = if setup & 0x80 == 0 {
+        Platform::Chip8
+    } else {
+        Platform::XoChip
+    };
+    let mut chip8 = machine(platform, preset, engine, program);
+    let mut reference =
+        (engine == Engine::Blocks).then(|| machine(platform, preset, Engine::Interpreter, program));
+    let memory_size = platform.memory_size();
+    for frame in 0..FRAMES {
+        run_frame(&mut chip8, timing);
+        assert!(
+            (chip8.program_counter as usize) < memory_size,
+            "PC {:#X} is outside memory",
+            chip8.program_counter
+        );
+        let stack = chip8.stack();
+        assert!(stack.len() <= stack.depth(), "the stack is past its depth");
+        if let Some(reference) = reference.as_mut() {
+            run_frame(reference, timing);
+            assert!(
+                state::save(&chip8) == state::save(reference),
+                "the block engine differs from the interpreter after frame {}",
+                frame
+            );
+        }
+        if chip8.fault().is_some() {
+            return;
+        }
+    }
+});
@@ -0,0 +1,338 @@
+//! A small assembler and disassembler for the mnemonics of Cowgod's CHIP-8
+//! technical reference, e.g. `LD V0, 0x12` or `DRW V0, V1, 5`.
+//!
+//! Disassembled programs assemble back to the same bytes: words that don't
+//! decode to an instruction are written as `DB` and jump targets stay plain
+//! addresses.
+
+use crate::rom::PROGRAM_START;
+use crate::{decode, Instruction};
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::SysAddr(nnn) => write!(f, "SYS {:#05X}", nnn),
+            Instruction::CLS => write!(f, "CLS"),
+            Instruction::RET => write!(f, "RET"),
+            Instruction::JPaddr(nnn) => write!(f, "JP {:#05X}", nnn),
+            Instruction::CallAddr(nnn) => write!(f, "CALL {:#05X}", nnn),
+            Instruction::SEVx(x, kk) => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Instruction::SNEVx(x, kk) => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Instruction::SEVxVy(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LDVx(x, kk) => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Instruction::ADDVx(x, kk) => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Instruction::LDVxVy(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::ORVxVy(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::ANDVxVy(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::XORVxVy(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::ADDVxVy(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SUBVxVy(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::SHRVx(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SUBN(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::SHL(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SNE(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LDI(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            Instruction::JPV0ADDR(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            Instruction::RNDVx(x, kk) => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Instruction::DRW(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SKP(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SKNP(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::LDVxDT(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::LDVxK(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::LDDTVx(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::LDSTVx(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AUDIO => write!(f, "AUDIO"),
+            Instruction::PITCHVx(x) => write!(f, "PITCH V{:X}", x),
+            Instruction::ADDIVx(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LDFVx(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::LDBVx(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::LDIVx(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::LDVxI(x) => write!(f, "LD V{:X}, [I]", x),
+        }
+    }
+}
+
+/// The two bytes `instruction` is stored as.
+pub fn opcode(instruction: &Instruction) -> u16 {
+    let xkk = |op: u16, x: u8, kk: u8| op << 12 | (x as u16) << 8 | kk as u16;
+    let xyn = |op: u16, x: u8, y: u8, n: u8| xkk(op, x, y << 4 | n);
+    match *instruction {
+        Instruction::SysAddr(nnn) => nnn,
+        Instruction::CLS => 0x00E0,
+        Instruction::RET => 0x00EE,
+        Instruction::JPaddr(nnn) => 0x1000 | nnn,
+        Instruction::CallAddr(nnn) => 0x2000 | nnn,
+        Instruction::SEVx(x, kk) => xkk(0x3, x, kk),
+        Instruction::SNEVx(x, kk) => xkk(0x4, x, kk),
+        Instruction::SEVxVy(x, y) => xyn(0x5, x, y, 0x0),
+        Instruction::LDVx(x, kk) => xkk(0x6, x, kk),
+        Instruction::ADDVx(x, kk) => xkk(0x7, x, kk),
+        Instruction::LDVxVy(x, y) => xyn(0x8, x, y, 0x0),
+        Instruction::ORVxVy(x, y) => xyn(0x8, x, y, 0x1),
+        Instruction::ANDVxVy(x, y) => xyn(0x8, x, y, 0x2),
+        Instruction::XORVxVy(x, y) => xyn(0x8, x, y, 0x3),
+        Instruction::ADDVxVy(x, y) => xyn(0x8, x, y, 0x4),
+        Instruction::SUBVxVy(x, y) => xyn(0x8, x, y, 0x5),
+        Instruction::SHRVx(x, y) => xyn(0x8, x, y, 0x6),
+        Instruction::SUBN(x, y) => xyn(0x8, x, y, 0x7),
+        Instruction::SHL(x, y) => xyn(0x8, x, y, 0xE),
+        Instruction::SNE(x, y) => xyn(0x9, x, y, 0x0),
+        Instruction::LDI(nnn) => 0xA000 | nnn,
+        Instruction::JPV0ADDR(nnn) => 0xB000 | nnn,
+        Instruction::RNDVx(x, kk) => xkk(0xC, x, kk),
+        Instruction::DRW(x, y, n) => xyn(0xD, x, y, n),
+        Instruction::SKP(x) => xkk(0xE, x, 0x9E),
+        Instruction::SKNP(x) => xkk(0xE, x, 0xA1),
+        Instruction::LDVxDT(x) => xkk(0xF, x, 0x07),
+        Instruction::LDVxK(x) => xkk(0xF, x, 0x0A),
+        Instruction::LDDTVx(x) => xkk(0xF, x, 0x15),
+        Instruction::LDSTVx(x) => xkk(0xF, x, 0x18),
+        Instruction::AUDIO => 0xF002,
+        Instruction::PITCHVx(x) => xkk(0xF, x, 0x3A),
+        Instruction::ADDIVx(x) => xkk(0xF, x, 0x1E),
+        Instruction::LDFVx(x) => xkk(0xF, x, 0x29),
+        Instruction::LDBVx(x) => xkk(0xF, x, 0x33),
+        Instruction::LDIVx(x) => xkk(0xF, x, 0x55),
+        Instruction::LDVxI(x) => xkk(0xF, x, 0x65),
+    }
+}
+
+/// One line per word, with its address and raw bytes in a comment.
+pub fn disassemble(program: &[u8]) -> String {
+    let mut text = String::new();
+    for (i, word) in program.chunks(2).enumerate() {
+        let instruction = match word {
+            [high, low] => decode(*high, *low)
+                .filter(|instruction| opcode(instruction) == u16::from_be_bytes([*high, *low])),
+            _ => None,
+        };
+        let code = match instruction {
+            Some(instruction) => instruction.to_string(),
+            None => {
+                let bytes: Vec<String> = word.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                format!("DB {}", bytes.join(", "))
+            }
+        };
+        let raw: String = word.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(
+            text,
+            "    {:<24}; {:03X}: {}",
+            code,
+            PROGRAM_START + i * 2,
+            raw
+        )
+        .unwrap();
+    }
+    text
+}
+
+enum Operand {
+    V(u8),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    B,
+    Value(u16),
+}
+
+/// A line that produces bytes, kept from the first pass until every label
+/// is known.
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles `source`, placing the program at 0x200. Errors name the line
+/// they were found on.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = PROGRAM_START;
+    for (i, line) in source.lines().enumerate() {
+        let mut code = line.split(';').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(format!("line {}: invalid label '{}'", i + 1, label));
+            }
+            if labels.insert(label, address as u16).is_some() {
+                return Err(format!(
+                    "line {}: label '{}' is defined twice",
+                    i + 1,
+                    label
+                ));
+            }
+            code = rest.trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+        let (mnemonic, operands) = code.split_once(char::is_whitespace).unwrap_or((code, ""));
+        let operands: Vec<&str> = operands
+            .split(',')
+            .map(str::trim)
+            .filter(|operand| !operand.is_empty())
+            .collect();
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        address += if mnemonic == "DB" { operands.len() } else { 2 };
+        statements.push(Statement {
+            line: i + 1,
+            mnemonic,
+            operands,
+        });
+    }
+    let mut program = Vec::new();
+    for statement in statements {
+        let bytes = encode(&statement, &labels)
+            .map_err(|error| format!("line {}: {}", statement.line, error))?;
+        program.extend(bytes);
+    }
+    Ok(program)
+}
+
+fn encode(statement: &Statement, labels: &HashMap<&str, u16>) -> Result<Vec<u8>, String> {
+    let operands = statement
+        .operands
+        .iter()
+        .map(|operand| parse_operand(operand, labels))
+        .collect::<Result<Vec<_>, _>>()?;
+    if statement.mnemonic == "DB" {
+        return operands
+            .iter()
+            .map(|operand| match operand {
+                Operand::Value(value) => byte(*value),
+                _ => Err("DB only takes numbers".to_string()),
+            })
+            .collect();
+    }
+    use Operand::*;
+    let instruction = match (statement.mnemonic.as_str(), operands.as_slice()) {
+        ("CLS", []) => Instruction::CLS,
+        ("RET", []) => Instruction::RET,
+        ("AUDIO", []) => Instruction::AUDIO,
+        ("SYS", [Value(nnn)]) => Instruction::SysAddr(address(*nnn)?),
+        ("JP", [Value(nnn)]) => Instruction::JPaddr(address(*nnn)?),
+        ("JP", [V(0), Value(nnn)]) => Instruction::JPV0ADDR(address(*nnn)?),
+        ("CALL", [Value(nnn)]) => Instruction::CallAddr(address(*nnn)?),
+        ("SE", [V(x), Value(kk)]) => Instruction::SEVx(*x, byte(*kk)?),
+        ("SE", [V(x), V(y)]) => Instruction::SEVxVy(*x, *y),
+        ("SNE", [V(x), Value(kk)]) => Instruction::SNEVx(*x, byte(*kk)?),
+        ("SNE", [V(x), V(y)]) => Instruction::SNE(*x, *y),
+        ("LD", [V(x), Value(kk)]) => Instruction::LDVx(*x, byte(*kk)?),
+        ("LD", [V(x), V(y)]) => Instruction::LDVxVy(*x, *y),
+        ("LD", [I, Value(nnn)]) => Instruction::LDI(address(*nnn)?),
+        ("LD", [V(x), DT]) => Instruction::LDVxDT(*x),
+        ("LD", [V(x), K]) => Instruction::LDVxK(*x),
+        ("LD", [DT, V(x)]) => Instruction::LDDTVx(*x),
+        ("LD", [ST, V(x)]) => Instruction::LDSTVx(*x),
+        ("LD", [F, V(x)]) => Instruction::LDFVx(*x),
+        ("LD", [B, V(x)]) => Instruction::LDBVx(*x),
+        ("LD", [IndirectI, V(x)]) => Instruction::LDIVx(*x),
+        ("LD", [V(x), IndirectI]) => Instruction::LDVxI(*x),
+        ("ADD", [V(x), Value(kk)]) => Instruction::ADDVx(*x, byte(*kk)?),
+        ("ADD", [V(x), V(y)]) => Instruction::ADDVxVy(*x, *y),
+        ("ADD", [I, V(x)]) => Instruction::ADDIVx(*x),
+        ("OR", [V(x), V(y)]) => Instruction::ORVxVy(*x, *y),
+        ("AND", [V(x), V(y)]) => Instruction::ANDVxVy(*x, *y),
+        ("XOR", [V(x), V(y)]) => Instruction::XORVxVy(*x, *y),
+        ("SUB", [V(x), V(y)]) => Instruction::SUBVxVy(*x, *y),
+        ("SUBN", [V(x), V(y)]) => Instruction::SUBN(*x, *y),
+        ("SHR", [V(x)]) => Instruction::SHRVx(*x, *x),
+        ("SHR", [V(x), V(y)]) => Instruction::SHRVx(*x, *y),
+        ("SHL", [V(x)]) => Instruction::SHL(*x, *x),
+        ("SHL", [V(x), V(y)]) => Instruction::SHL(*x, *y),
+        ("RND", [V(x), Value(kk)]) => Instruction::RNDVx(*x, byte(*kk)?),
+        ("DRW", [V(x), V(y), Value(n)]) if *n <= 0xF => Instruction::DRW(*x, *y, *n as u8),
+        ("SKP", [V(x)]) => Instruction::SKP(*x),
+        ("SKNP", [V(x)]) => Instruction::SKNP(*x),
+        ("PITCH", [V(x)]) => Instruction::PITCHVx(*x),
+        _ => {
+            return Err(format!(
+                "'{} {}' isn't an instruction",
+                statement.mnemonic,
+                statement.operands.join(", ")
+            ))
+        }
+    };
+    Ok(opcode(&instruction).to_be_bytes().to_vec())
+}
+
+fn parse_operand(operand: &str, labels: &HashMap<&str, u16>) -> Result<Operand, String> {
+    let upper = operand.to_ascii_uppercase();
+    let register = upper
+        .strip_prefix('V')
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| u8::from_str_radix(digit, 16).ok());
+    if let Some(x) = register {
+        return Ok(Operand::V(x));
+    }
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => {
+            let value = if let Some(hex) = upper.strip_prefix("0X") {
+                u16::from_str_radix(hex, 16).ok()
+            } else if let Some(binary) = upper.strip_prefix("0B") {
+                u16::from_str_radix(binary, 2).ok()
+            } else if operand.starts_with(|c: char| c.is_ascii_digit()) {
+                operand.parse().ok()
+            } else {
+                Some(
+                    *labels
+                        .get(operand)
+                        .ok_or_else(|| format!("unknown label '{}'", operand))?,
+                )
+            };
+            Operand::Value(value.ok_or_else(|| format!("invalid number '{}'", operand))?)
+        }
+    };
+    Ok(operand)
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("{:#X} doesn't fit in a byte", value))
+}
+
+fn address(value: u16) -> Result<u16, String> {
+    if value > 0xFFF {
+        return Err(format!("{:#X} is past the end of memory", value));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn bundled_roms_survive_disassembling_and_assembling() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut roms = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if !matches!(extension, Some("ch8" | "rom")) {
+                continue;
+            }
+            let rom = std::fs::read(&path).unwrap();
+            let source = disassemble(&rom);
+            let assembled =
+                assemble(&source).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+            assert!(assembled == rom, "{} changed", path.display());
+            roms += 1;
+        }
+        assert_eq!(roms, 13);
+    }
+}
@@ -0,0 +1,490 @@
+use crate::frontend::Audio;
+use std::f32::consts::PI;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+
+pub const SAMPLE_RATE: u32 = 44100;
+/// The sound timer counts down at 60Hz, so audio is produced in frames of this size.
+pub const SAMPLES_PER_FRAME: usize = SAMPLE_RATE as usize / 60;
+
+/// Receives mono samples in the range -1.0..=1.0 at `SAMPLE_RATE`.
+pub trait AudioSink {
+    fn play(&mut self, samples: &[f32]) -> io::Result<()>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    /// `phase` is the position inside one period, in 0.0..1.0.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!(
+                "unknown waveform '{}', expected square, triangle, sawtooth or sine",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+/// XO-CHIP sound: a 128 bit pattern loaded by F002, played back one bit per
+/// sample at a rate set by the pitch register (Fx3A).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pattern {
+    pub bits: [u8; 16],
+    pub pitch: u8,
+}
+
+impl Pattern {
+    /// Bits per second, 4000Hz at the default pitch of 64.
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+    fn bit(&self, index: usize) -> bool {
+        let index = index % 128;
+        (self.bits[index / 8] >> (7 - index % 8)) & 1 == 1
+    }
+    /// Averages the 1-bit signal over `start..start + width` (measured in bits).
+    /// Integrating over the whole output sample acts as a box filter, which
+    /// keeps the sharp edges of the pattern from aliasing at high pitches.
+    fn average(&self, start: f64, width: f64) -> f32 {
+        let end = start + width;
+        let mut position = start;
+        let mut high = 0.0;
+        while position < end {
+            let next = (position.floor() + 1.0).min(end);
+            if self.bit(position as usize) {
+                high += next - position;
+            }
+            position = next;
+        }
+        (2.0 * high / width - 1.0) as f32
+    }
+}
+
+/// Turns the state of the sound timer into samples, one 60Hz frame at a time.
+pub struct Beeper {
+    pub tone: Tone,
+    phase: f32,
+    pattern_position: f64,
+    buffer: Vec<f32>,
+    sink: Box<dyn AudioSink + Send>,
+}
+
+impl Beeper {
+    pub fn new(tone: Tone, sink: Box<dyn AudioSink + Send>) -> Self {
+        Self {
+            tone,
+            phase: 0.0,
+            pattern_position: 0.0,
+            buffer: vec![0.0; SAMPLES_PER_FRAME],
+            sink,
+        }
+    }
+    fn render(&mut self, sound_on: bool, pattern: Option<Pattern>) -> &[f32] {
+        if !sound_on {
+            self.buffer.fill(0.0);
+            self.phase = 0.0;
+            self.pattern_position = 0.0;
+            return &self.buffer;
+        }
+        match pattern {
+            Some(pattern) => {
+                let step = pattern.playback_rate() / SAMPLE_RATE as f64;
+                for sample in self.buffer.iter_mut() {
+                    *sample = pattern.average(self.pattern_position, step) * self.tone.volume;
+                    self.pattern_position = (self.pattern_position + step) % 128.0;
+                }
+            }
+            None => {
+                let step = self.tone.frequency / SAMPLE_RATE as f32;
+                for sample in self.buffer.iter_mut() {
+                    *sample = self.tone.waveform.sample(self.phase) * self.tone.volume;
+                    self.phase = (self.phase + step).fract();
+                }
+            }
+        }
+        &self.buffer
+    }
+}
+
+impl Audio for Beeper {
+    fn tick(&mut self, sound_on: bool, pattern: Option<Pattern>) -> io::Result<()> {
+        self.render(sound_on, pattern);
+        self.sink.play(&self.buffer)
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// Writes 16 bit mono PCM to a WAV file. The header is rewritten after every
+/// frame so the file stays valid even if the process exits without warning.
+pub struct WavSink<W: Write + Seek> {
+    out: W,
+    samples: u32,
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut out: W) -> io::Result<Self> {
+        write_wav_header(&mut out, 0)?;
+        Ok(Self { out, samples: 0 })
+    }
+    fn update_header(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        write_wav_header(&mut self.out, self.samples)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()
+    }
+}
+
+fn write_wav_header<W: Write>(out: &mut W, samples: u32) -> io::Result<()> {
+    let data_size = samples * 2;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_size).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?; // PCM
+    out.write_all(&1u16.to_le_bytes())?; // mono
+    out.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    out.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_size.to_le_bytes())
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn play(&mut self, samples: &[f32]) -> io::Result<()> {
+        for sample in samples {
+            self.out.write_all(&to_i16(*sample).to_le_bytes())?;
+        }
+        self.samples += samples.len() as u32;
+        self.update_header()
+    }
+}
+
+/// Streams raw PCM into `aplay`, which is available on most linux desktops.
+pub struct AplaySink {
+    child: Child,
+}
+
+impl AplaySink {
+    pub fn new() -> io::Result<Self> {
+        let child = Command::new("aplay")
+            .args(["-q", "-t", "raw", "-f", "S16_LE", "-c", "1", "-r"])
+            .arg(SAMPLE_RATE.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(Self { child })
+    }
+}
+
+impl AudioSink for AplaySink {
+    fn play(&mut self, samples: &[f32]) -> io::Result<()> {
+        let stdin = self
+            .child
+            .stdin
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "aplay closed stdin"))?;
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| to_i16(*sample).to_le_bytes())
+            .collect();
+        stdin.write_all(&bytes)
+    }
+}
+
+impl Drop for AplaySink {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// Fallback when there is no audio device: rings the terminal bell once
+/// whenever a beep starts.
+pub struct BellSink<W: Write> {
+    out: W,
+    playing: bool,
+}
+
+impl<W: Write> BellSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            playing: false,
+        }
+    }
+}
+
+impl<W: Write> AudioSink for BellSink<W> {
+    fn play(&mut self, samples: &[f32]) -> io::Result<()> {
+        let playing = samples.iter().any(|sample| *sample != 0.0);
+        if playing && !self.playing {
+            self.out.write_all(b"\x07")?;
+            self.out.flush()?;
+        }
+        self.playing = playing;
+        Ok(())
+    }
+}
+
+/// Discards everything, for runs that shouldn't make a sound.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&mut self, _samples: &[f32]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::QuirkPreset;
+    use crate::rom::Platform;
+    use crate::{run_instructions, tick_timers, Chip8};
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+
+    /// An in-memory file that stays readable after a `Beeper` takes the sink.
+    #[derive(Clone, Default)]
+    struct SharedFile(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+    impl SharedFile {
+        fn bytes(&self) -> Vec<u8> {
+            self.0.lock().unwrap().get_ref().clone()
+        }
+    }
+
+    impl Write for SharedFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for SharedFile {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            self.0.lock().unwrap().seek(position)
+        }
+    }
+
+    fn u32_at(wav: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(wav[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// Square wave samples at the default volume.
+    const HIGH: i16 = 8191;
+    const LOW: i16 = -8191;
+
+    fn pattern_wav(frames: usize) -> Vec<u8> {
+        let tone = Tone {
+            volume: 0.5,
+            ..Tone::default()
+        };
+        let pattern = Pattern {
+            bits: [
+                0xF0, 0x0F, 0xAA, 0x00, 0xFF, 0x81, 0x3C, 0x55, 0xF0, 0x0F, 0xAA, 0x00, 0xFF, 0x81,
+                0x3C, 0x55,
+            ],
+            pitch: 100,
+        };
+        let mut beeper = Beeper::new(tone, Box::new(NullSink));
+        let mut out = Cursor::new(Vec::new());
+        {
+            let mut wav = WavSink::new(&mut out).unwrap();
+            for _ in 0..frames {
+                wav.play(beeper.render(true, Some(pattern))).unwrap();
+            }
+        }
+        out.into_inner()
+    }
+
+    fn samples(wav: &[u8]) -> Vec<i16> {
+        wav[44..]
+            .chunks(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn pattern_wav_matches_known_samples() {
+        let wav = pattern_wav(2);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), 2 * 2 * SAMPLES_PER_FRAME as u32);
+        let samples = samples(&wav);
+        assert_eq!(
+            samples[24..30],
+            [16383, 16383, -9108, -16383, -16383, -16383]
+        );
+        assert_eq!(
+            samples[100..132],
+            [
+                16383, 16383, 16383, 16383, 16383, 16383, 16383, 16383, 16383, 16383, 16383, -1848,
+                -16383, -16383, -16383, -16383, -16383, -16353, 16383, 16383, 16383, 16383, 16383,
+                16383, 1788, -16383, -16383, -16383, -16383, -16383, -16383, 12776
+            ]
+        );
+        // playback continues where the previous frame stopped
+        assert_eq!(
+            samples[735..751],
+            [
+                -16383, -16383, -16383, -16383, -16383, -16383, -16383, -16383, -16383, -16383,
+                -16383, -16383, 5652, 16383, 16383, 16383
+            ]
+        );
+    }
+
+    #[test]
+    fn pitch_register_doubles_rate_every_48_steps() {
+        let rate = |pitch| {
+            Pattern {
+                bits: [0; 16],
+                pitch,
+            }
+            .playback_rate()
+        };
+        assert_eq!(rate(64), 4000.0);
+        assert_eq!(rate(112), 8000.0);
+        assert_eq!(rate(16), 2000.0);
+    }
+
+    #[test]
+    fn wav_header_counts_the_samples() {
+        let file = SharedFile::default();
+        let mut beeper = Beeper::new(
+            Tone::default(),
+            Box::new(WavSink::new(file.clone()).unwrap()),
+        );
+        assert_eq!(file.bytes().len(), 44);
+        for sound_on in [true, false, true] {
+            beeper.tick(sound_on, None).unwrap();
+        }
+        let wav = file.bytes();
+        let data_size = 3 * SAMPLES_PER_FRAME as u32 * 2;
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(u32_at(&wav, 4), 36 + data_size);
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&wav, 16), 16);
+        // PCM, mono
+        assert_eq!(&wav[20..24], [1, 0, 1, 0]);
+        assert_eq!(u32_at(&wav, 24), SAMPLE_RATE);
+        assert_eq!(u32_at(&wav, 28), SAMPLE_RATE * 2);
+        // 2 bytes a sample, 16 bits
+        assert_eq!(&wav[32..36], [2, 0, 16, 0]);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(u32_at(&wav, 40), data_size);
+        assert_eq!(wav.len(), 44 + data_size as usize);
+    }
+
+    #[test]
+    fn beeps_start_at_the_top_of_the_wave() {
+        let file = SharedFile::default();
+        let mut beeper = Beeper::new(
+            Tone::default(),
+            Box::new(WavSink::new(file.clone()).unwrap()),
+        );
+        beeper.tick(true, None).unwrap();
+        beeper.tick(false, None).unwrap();
+        beeper.tick(true, None).unwrap();
+        let samples = samples(&file.bytes());
+        // 440Hz at 44100Hz is a little over 50 samples high, then 50 low
+        assert!(samples[..51].iter().all(|sample| *sample == HIGH));
+        assert!(samples[51..101].iter().all(|sample| *sample == LOW));
+        let off = SAMPLES_PER_FRAME..2 * SAMPLES_PER_FRAME;
+        assert!(samples[off].iter().all(|sample| *sample == 0));
+        // the second beep starts over instead of carrying on the first
+        assert_eq!(
+            samples[2 * SAMPLES_PER_FRAME..],
+            samples[..SAMPLES_PER_FRAME]
+        );
+    }
+
+    #[test]
+    fn tone_lasts_as_many_frames_as_the_sound_timer() {
+        let file = SharedFile::default();
+        let mut beeper = Beeper::new(
+            Tone::default(),
+            Box::new(WavSink::new(file.clone()).unwrap()),
+        );
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        // LD V0, 3; LD ST, V0; JP 0x204
+        chip8.load(&[0x60, 0x03, 0xF0, 0x18, 0x12, 0x04]);
+        for _ in 0..5 {
+            run_instructions(&mut chip8, 10);
+            let (sound_on, pattern) = tick_timers(&mut chip8);
+            beeper.tick(sound_on, pattern).unwrap();
+        }
+        let samples = samples(&file.bytes());
+        assert_eq!(samples.len(), 5 * SAMPLES_PER_FRAME);
+        let (tone, silence) = samples.split_at(3 * SAMPLES_PER_FRAME);
+        assert!(tone.iter().all(|sample| *sample == HIGH || *sample == LOW));
+        assert!(silence.iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn silent_while_sound_timer_is_zero() {
+        let pattern = Pattern {
+            bits: [0xFF; 16],
+            pitch: 64,
+        };
+        let mut beeper = Beeper::new(Tone::default(), Box::new(NullSink));
+        assert!(beeper
+            .render(false, Some(pattern))
+            .iter()
+            .all(|s| *s == 0.0));
+        assert!(beeper.render(false, None).iter().all(|s| *s == 0.0));
+    }
+}
@@ -0,0 +1,154 @@
+//! The block engine, for when speed matters more than watching every fetch.
+//! A straight run of instructions is decoded once into a block, which then
+//! runs without fetching or decoding anything. Blocks end after anything
+//! that jumps, skips, calls, draws, waits for a key or writes memory, so a
+//! write into translated code always lands between blocks and the next
+//! lookup sees it.
+
+use crate::{execute, step, wrap, Chip8, Instruction};
+use std::fmt;
+use std::str::FromStr;
+
+/// Keeps translation cheap when a program runs into a long stretch of data.
+const MAX_LENGTH: usize = 64;
+
+/// How `run_instructions` executes a program. Both give the same results.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Engine {
+    /// Fetches and decodes every instruction, through the decoded cache.
+    #[default]
+    Interpreter,
+    Blocks,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Engine::Interpreter => write!(f, "interpreter"),
+            Engine::Blocks => write!(f, "blocks"),
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Engine::Interpreter),
+            "blocks" => Ok(Engine::Blocks),
+            _ => Err(format!(
+                "unknown engine '{}', expected interpreter or blocks",
+                s
+            )),
+        }
+    }
+}
+
+pub(crate) struct Blocks {
+    /// By the address they start at.
+    blocks: Vec<Option<Box<[Instruction]>>>,
+    /// Every byte some block was translated from.
+    translated: Vec<bool>,
+    /// Goes up whenever the blocks are thrown away, so a running block can
+    /// tell it shouldn't be put back.
+    generation: u64,
+}
+
+impl Blocks {
+    pub(crate) fn new(memory_size: usize) -> Blocks {
+        Blocks {
+            blocks: vec![None; memory_size],
+            translated: vec![false; memory_size],
+            generation: 0,
+        }
+    }
+
+    /// Throws every block away when `address` is code, since finding just
+    /// the blocks covering it isn't worth it for how rarely that happens.
+    pub(crate) fn invalidate(&mut self, address: u16) {
+        if self.translated[address as usize % self.translated.len()] {
+            self.clear();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.blocks.fill(None);
+        self.translated.fill(false);
+        self.generation += 1;
+    }
+}
+
+fn ends_block(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::SysAddr(_)
+            | Instruction::RET
+            | Instruction::JPaddr(_)
+            | Instruction::CallAddr(_)
+            | Instruction::SEVx(..)
+            | Instruction::SNEVx(..)
+            | Instruction::SEVxVy(..)
+            | Instruction::SNE(..)
+            | Instruction::JPV0ADDR(_)
+            | Instruction::DRW(..)
+            | Instruction::SKP(_)
+            | Instruction::SKNP(_)
+            | Instruction::LDVxK(_)
+            | Instruction::LDBVx(_)
+            | Instruction::LDIVx(_)
+    )
+}
+
+/// Decodes from `start` up to the end of the block. Empty when the first
+/// instruction doesn't decode.
+fn translate(chip8: &mut Chip8, start: u16) -> Box<[Instruction]> {
+    let mut block = Vec::new();
+    let mut address = start;
+    while block.len() < MAX_LENGTH {
+        let Some(instruction) = chip8.decode_at(address) else {
+            break;
+        };
+        block.push(instruction);
+        let blocks = chip8.blocks.as_mut().unwrap();
+        let size = blocks.translated.len();
+        blocks.translated[address as usize % size] = true;
+        blocks.translated[(address as usize + 1) % size] = true;
+        if ends_block(instruction) {
+            break;
+        }
+        address = ((address as usize + 2) % size) as u16;
+    }
+    block.into()
+}
+
+/// Runs up to `instructions` instructions a block at a time, like
+/// `run_instructions`. The last block is cut short when it would go over.
+pub(crate) fn run(chip8: &mut Chip8, instructions: u32) -> u32 {
+    let mut remaining = instructions as usize;
+    // Dxyn, calls and returns end blocks, so checking between them is enough
+    while remaining > 0 && !chip8.stopped() {
+        let start = chip8.program_counter;
+        let blocks = chip8.blocks.as_mut().unwrap();
+        let index = wrap(start as usize, blocks.blocks.len());
+        let generation = blocks.generation;
+        // taken out while it runs, since the instructions need the whole machine
+        let block = match blocks.blocks[index].take() {
+            Some(block) => block,
+            None => translate(chip8, start),
+        };
+        if block.is_empty() {
+            step(chip8);
+            remaining -= 1;
+        } else {
+            for &instruction in block.iter().take(remaining) {
+                execute(chip8, Some(instruction));
+            }
+            remaining -= block.len().min(remaining);
+        }
+        let blocks = chip8.blocks.as_mut().unwrap();
+        if blocks.generation == generation {
+            blocks.blocks[index] = Some(block);
+        }
+    }
+    instructions - remaining as u32
+}
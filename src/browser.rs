@@ -0,0 +1,210 @@
+use chip8::rom::Rom;
+use crossterm::{
+    cursor,
+    event::{read, Event, KeyCode, KeyEventKind},
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType},
+    QueueableCommand,
+};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Files that look like programs, by extension.
+const EXTENSIONS: [&str; 6] = ["ch8", "c8", "rom", "sc8", "xo8", "gif"];
+const LIST_WIDTH: usize = 32;
+
+struct Item {
+    path: PathBuf,
+    name: String,
+    /// Why the file can't be played, instead of a rom.
+    rom: Result<Rom, String>,
+}
+
+/// A menu of the roms in a directory. Typing filters the list by title and
+/// file name.
+pub struct Browser {
+    dir: PathBuf,
+    items: Vec<Item>,
+    filter: String,
+    selected: usize,
+    scroll: usize,
+    message: Option<String>,
+}
+
+impl Browser {
+    pub fn scan(dir: &Path) -> io::Result<Browser> {
+        let mut items = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let known = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+                });
+            if !path.is_file() || !known {
+                continue;
+            }
+            let rom = Rom::load(&path, None).map_err(|error| error.to_string());
+            let name = match &rom {
+                Ok(rom) => rom.name(),
+                Err(_) => path.file_name().unwrap().to_string_lossy().into_owned(),
+            };
+            items.push(Item { path, name, rom });
+        }
+        items.sort_by_key(|item| item.name.to_lowercase());
+        Ok(Browser {
+            dir: dir.to_path_buf(),
+            items,
+            filter: String::new(),
+            selected: 0,
+            scroll: 0,
+            message: None,
+        })
+    }
+
+    /// Shown under the list until the next key press.
+    pub fn message(&mut self, text: impl Into<String>) {
+        self.message = Some(text.into());
+    }
+
+    fn visible(&self) -> Vec<&Item> {
+        let filter = self.filter.to_lowercase();
+        self.items
+            .iter()
+            .filter(|item| {
+                item.name.to_lowercase().contains(&filter)
+                    || item.path.to_string_lossy().to_lowercase().contains(&filter)
+            })
+            .collect()
+    }
+
+    /// Shows the menu until a rom is picked, or returns None when the user
+    /// leaves with Esc.
+    pub fn choose<W: Write>(&mut self, out: &mut W) -> io::Result<Option<PathBuf>> {
+        loop {
+            self.draw(out)?;
+            let Event::Key(event) = read()? else {
+                continue;
+            };
+            if event.kind != KeyEventKind::Press {
+                continue;
+            }
+            self.message = None;
+            let count = self.visible().len();
+            match event.code {
+                KeyCode::Esc if !self.filter.is_empty() => self.filter.clear(),
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => {
+                    if let Some(item) = self.visible().get(self.selected) {
+                        return Ok(Some(item.path.clone()));
+                    }
+                }
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+                KeyCode::PageUp => self.selected = self.selected.saturating_sub(10),
+                KeyCode::PageDown => {
+                    self.selected = (self.selected + 10).min(count.saturating_sub(1))
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.selected = 0;
+                }
+                _ => {}
+            }
+            self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+        }
+    }
+
+    fn draw<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let list_rows = (rows as usize).saturating_sub(4).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_rows {
+            self.scroll = self.selected + 1 - list_rows;
+        }
+        out.queue(Clear(ClearType::All))?
+            .queue(cursor::MoveTo(0, 0))?
+            .queue(SetAttribute(Attribute::Bold))?
+            .queue(Print(format!("roms in {}", self.dir.display())))?
+            .queue(SetAttribute(Attribute::Reset))?
+            .queue(cursor::MoveTo(0, 1))?
+            .queue(Print(format!("filter: {}", self.filter)))?;
+        let visible = self.visible();
+        for (row, (i, item)) in visible
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(list_rows)
+            .enumerate()
+        {
+            let marker = if i == self.selected { '>' } else { ' ' };
+            let name: String = item.name.chars().take(LIST_WIDTH - 2).collect();
+            out.queue(cursor::MoveTo(0, row as u16 + 3))?;
+            if i == self.selected {
+                out.queue(SetAttribute(Attribute::Reverse))?;
+            }
+            out.queue(Print(format!(
+                "{} {:<width$}",
+                marker,
+                name,
+                width = LIST_WIDTH - 2
+            )))?
+            .queue(SetAttribute(Attribute::Reset))?;
+        }
+        if visible.is_empty() {
+            out.queue(cursor::MoveTo(2, 3))?
+                .queue(Print("no roms match"))?;
+        }
+        if let Some(item) = visible.get(self.selected) {
+            let preview_width = (columns as usize).saturating_sub(LIST_WIDTH + 2);
+            for (row, line) in preview(item).iter().enumerate() {
+                let line: String = line.chars().take(preview_width).collect();
+                out.queue(cursor::MoveTo(LIST_WIDTH as u16 + 2, row as u16 + 3))?
+                    .queue(Print(line))?;
+            }
+        }
+        let footer = self.message.clone().unwrap_or_else(|| {
+            "up/down select | enter play | type to filter | esc quit".to_string()
+        });
+        out.queue(cursor::MoveTo(0, rows.saturating_sub(1)))?
+            .queue(Print(footer))?;
+        out.flush()
+    }
+}
+
+/// What the preview pane says about the selected file.
+fn preview(item: &Item) -> Vec<String> {
+    let mut lines = vec![format!("file      {}", item.path.display())];
+    let rom = match &item.rom {
+        Ok(rom) => rom,
+        Err(error) => {
+            lines.push(format!("error     {}", error));
+            return lines;
+        }
+    };
+    match rom.entry {
+        Some(entry) => {
+            lines.push(format!("title     {}", entry.title));
+            lines.push(format!("author    {}", entry.author));
+            lines.push(format!("quirks    {}", entry.quirks));
+            lines.push(format!("ipf       {}", entry.ipf));
+        }
+        None => lines.push("not in the rom database".to_string()),
+    }
+    if rom.cartridge.is_some() {
+        lines.push("format    Octo cartridge".to_string());
+    }
+    lines.push(format!("platform  {}", rom.platform));
+    lines.push(format!("size      {} bytes", rom.bytes.len()));
+    lines.push(format!("sha1      {}", rom.sha1));
+    for warning in &rom.warnings {
+        lines.push(format!("warning   {}", warning));
+    }
+    lines
+}
@@ -0,0 +1,39 @@
+//! How the interpreter reaches memory. Instructions only read and write
+//! through a `Bus`, so read-only regions, watchpoints or access counters can
+//! be put between them and the bytes without touching instruction code.
+//! `Chip8::set_bus` swaps one in; `Ram` is the default.
+
+/// The chip8's address space.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// Every byte, for loading programs and save states. Going through this
+    /// doesn't count as an access and ignores any protection.
+    fn memory(&self) -> &[u8];
+    fn memory_mut(&mut self) -> &mut [u8];
+}
+
+/// Plain memory. Addresses past the end wrap around to the start.
+pub struct Ram(Vec<u8>);
+
+impl Ram {
+    pub fn new(size: usize) -> Ram {
+        Ram(vec![0; size])
+    }
+}
+
+impl Bus for Ram {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0[address as usize % self.0.len()]
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        let size = self.0.len();
+        self.0[address as usize % size] = value;
+    }
+    fn memory(&self) -> &[u8] {
+        &self.0
+    }
+    fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
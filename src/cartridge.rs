@@ -0,0 +1,321 @@
+//! Octo "cartridges": GIF images that carry a program and its options in
+//! the lowest two bits of every pixel's palette index.
+//!
+//! The hidden payload starts with a 32 bit big endian length followed by
+//! that many bytes of UTF-8 JSON: `{"program": ..., "options": {...}}`.
+//!
+//! Only pre-assembled cartridges are supported: the program has to be a list
+//! of bytes, or text made of nothing but byte literals. Most cartridges Octo
+//! saves hold Octo source instead, which is rejected; those need to be
+//! exported from Octo as a .ch8 first.
+
+use crate::quirks::{QuirkPreset, Quirks};
+use crate::render::Palette;
+use crate::rom::Platform;
+use serde_json::Value;
+
+pub struct Cartridge {
+    pub program: Vec<u8>,
+    pub ipf: Option<u32>,
+    pub quirks: Option<Quirks>,
+    pub palette: Option<Palette>,
+    pub platform: Option<Platform>,
+}
+
+pub fn is_gif(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+}
+
+pub fn decode(gif: &[u8]) -> Result<Cartridge, String> {
+    let payload = hidden_bytes(gif)?;
+    if payload.len() < 4 {
+        return Err("cartridge is too small to hold a program".to_string());
+    }
+    let size = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    let json = payload
+        .get(4..4 + size)
+        .ok_or_else(|| format!("cartridge claims {} bytes but holds fewer", size))?;
+    let json: Value = serde_json::from_slice(json)
+        .map_err(|error| format!("cartridge data isn't valid JSON: {}", error))?;
+    let program = match &json["program"] {
+        Value::String(source) => assemble_literals(source)?,
+        Value::Array(bytes) => bytes
+            .iter()
+            .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or("cartridge program has values that aren't bytes")?,
+        _ => return Err("cartridge has no program".to_string()),
+    };
+    let options = &json["options"];
+    Ok(Cartridge {
+        program,
+        ipf: options["tickrate"].as_u64().map(|ipf| ipf as u32),
+        quirks: quirks(options),
+        palette: palette(options),
+        platform: platform(options),
+    })
+}
+
+/// Collects the low two bits of every pixel, four pixels to a byte, most
+/// significant bits first.
+fn hidden_bytes(gif: &[u8]) -> Result<Vec<u8>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(gif)
+        .map_err(|error| format!("couldn't decode GIF: {}", error))?;
+    let mut bytes = Vec::new();
+    let mut byte = 0u8;
+    let mut pairs = 0;
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|error| format!("couldn't decode GIF: {}", error))?
+    {
+        for index in frame.buffer.iter() {
+            byte = (byte << 2) | (index & 3);
+            pairs += 1;
+            if pairs == 4 {
+                bytes.push(byte);
+                byte = 0;
+                pairs = 0;
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Reads a program written as byte literals. Anything else is Octo source,
+/// which would need an Octo compiler.
+fn assemble_literals(source: &str) -> Result<Vec<u8>, String> {
+    let mut program = Vec::new();
+    for line in source.lines() {
+        let code = line.split('#').next().unwrap_or_default();
+        for token in code.split_whitespace() {
+            let value = if let Some(hex) = token.strip_prefix("0x") {
+                u8::from_str_radix(hex, 16).ok()
+            } else if let Some(binary) = token.strip_prefix("0b") {
+                u8::from_str_radix(binary, 2).ok()
+            } else {
+                token.parse().ok()
+            };
+            program.push(value.ok_or_else(|| {
+                format!(
+                    "cartridge holds Octo source ('{}') and only pre-assembled cartridges are \
+                     supported, export it from Octo as a .ch8 first",
+                    token
+                )
+            })?);
+        }
+    }
+    Ok(program)
+}
+
+/// Octo stores each quirk as a separate flag; flags that are missing keep
+/// the COSMAC VIP behaviour.
+fn quirks(options: &Value) -> Option<Quirks> {
+    if !options.is_object() {
+        return None;
+    }
+    let flag = |name: &str| options[name].as_bool();
+    let mut quirks = QuirkPreset::Vip.quirks();
+    if let Some(shift) = flag("shiftQuirks") {
+        quirks.shift_vx = shift;
+    }
+    if let Some(load_store) = flag("loadStoreQuirks") {
+        quirks.memory_increment = !load_store;
+    }
+    if let Some(logic) = flag("logicQuirks") {
+        quirks.vf_reset = logic;
+    }
+    if let Some(clip) = flag("clipQuirks") {
+        quirks.clipping = clip;
+    }
+    if let Some(jump) = flag("jumpQuirks") {
+        quirks.jump_vx = jump;
+    }
+    if let Some(vblank) = flag("vBlankQuirks") {
+        quirks.display_wait = vblank;
+    }
+    Some(quirks)
+}
+
+/// Octo picks the platform through the largest program it allows.
+fn platform(options: &Value) -> Option<Platform> {
+    match options["maxSize"].as_u64()? {
+        3216 => Some(Platform::Schip),
+        3583 => Some(Platform::Chip8),
+        65024 => Some(Platform::XoChip),
+        _ => None,
+    }
+}
+
+fn palette(options: &Value) -> Option<Palette> {
+    let foreground = options["fillColor"].as_str()?;
+    let background = options["backgroundColor"].as_str()?;
+    format!("{},{}", foreground, background).parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use std::path::{Path, PathBuf};
+
+    /// A cartridge of `payload`, with the high bits of every index set so
+    /// only the low two can carry the data.
+    fn cartridge(payload: &[u8]) -> Vec<u8> {
+        const WIDTH: usize = 32;
+        let mut pixels = Vec::new();
+        for byte in payload {
+            for shift in [6, 4, 2, 0] {
+                pixels.push(0b1100 | (byte >> shift) & 3);
+            }
+        }
+        pixels.resize(pixels.len().div_ceil(WIDTH) * WIDTH, 0b1100);
+        let palette: Vec<u8> = (0..16).flat_map(|i| [i * 16, i * 16, i * 16]).collect();
+        let mut gif = Vec::new();
+        {
+            let height = (pixels.len() / WIDTH) as u16;
+            let mut encoder = gif::Encoder::new(&mut gif, WIDTH as u16, height, &palette).unwrap();
+            let frame = gif::Frame {
+                width: WIDTH as u16,
+                height,
+                buffer: pixels.into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        gif
+    }
+
+    fn with_length(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        payload
+    }
+
+    fn fixture_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/cartridges")
+            .join(name)
+    }
+
+    /// Reads `tests/cartridges/sprite.gif`, a cartridge with a short
+    /// assembled program and every option Octo writes.
+    fn fixture() -> Vec<u8> {
+        std::fs::read(fixture_path("sprite.gif")).unwrap()
+    }
+
+    #[test]
+    fn low_bits_of_each_index_make_the_bytes() {
+        let gif = cartridge(&[0b00_01_10_11, 0xA5]);
+        assert!(is_gif(&gif));
+        let bytes = hidden_bytes(&gif).unwrap();
+        assert_eq!(bytes[..2], [0b00_01_10_11, 0xA5]);
+        assert!(bytes[2..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn payload_starts_with_a_big_endian_length() {
+        let bytes = hidden_bytes(&fixture()).unwrap();
+        let length = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+        // long enough that the length needs more than its lowest byte
+        assert!(length > 0xFF);
+        assert_eq!(bytes[4], b'{');
+        assert_eq!(bytes[4 + length - 1], b'}');
+        assert!(serde_json::from_slice::<Value>(&bytes[4..4 + length]).is_ok());
+    }
+
+    #[test]
+    fn fixture_has_its_program_and_options() {
+        let cartridge = decode(&fixture()).unwrap();
+        assert_eq!(
+            cartridge.program,
+            [
+                0x00, 0xE0, 0xA2, 0x0C, 0x60, 0x00, 0x61, 0x00, 0xD0, 0x15, 0x12, 0x08, 0xF0, 0x90,
+                0x90, 0x90, 0xF0
+            ]
+        );
+        assert_eq!(cartridge.ipf, Some(20));
+        assert_eq!(cartridge.platform, Some(Platform::Schip));
+        assert_eq!(cartridge.palette, Some("ffaa00,101010".parse().unwrap()));
+        let quirks = cartridge.quirks.unwrap();
+        assert!(quirks.shift_vx);
+        assert!(!quirks.memory_increment);
+        assert!(!quirks.vf_reset);
+        assert!(quirks.clipping);
+        assert!(quirks.jump_vx);
+        assert!(!quirks.display_wait);
+    }
+
+    #[test]
+    fn roms_load_from_cartridges() {
+        let rom = Rom::load(fixture_path("sprite.gif"), None).unwrap();
+        assert!(rom.cartridge.is_some());
+        assert_eq!(rom.bytes.len(), 17);
+        assert_eq!(rom.platform, Platform::Schip);
+    }
+
+    #[test]
+    fn missing_quirk_flags_keep_the_vip_behaviour() {
+        let vip = QuirkPreset::Vip.quirks();
+        assert_eq!(quirks(&serde_json::json!({})), Some(vip));
+        assert_eq!(
+            quirks(&serde_json::json!({ "loadStoreQuirks": true })),
+            Some(Quirks {
+                memory_increment: false,
+                ..vip
+            })
+        );
+        assert_eq!(quirks(&Value::Null), None);
+    }
+
+    #[test]
+    fn max_size_picks_the_platform() {
+        let platform_for = |size: u64| platform(&serde_json::json!({ "maxSize": size }));
+        assert_eq!(platform_for(3583), Some(Platform::Chip8));
+        assert_eq!(platform_for(3216), Some(Platform::Schip));
+        assert_eq!(platform_for(65024), Some(Platform::XoChip));
+        assert_eq!(platform_for(4096), None);
+        assert_eq!(platform(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn byte_literal_source_is_assembled() {
+        let gif = cartridge(&with_length(
+            r#"{"program": "0x00 0xE0 # clear\n0b00010010 0", "options": {}}"#,
+        ));
+        assert_eq!(decode(&gif).unwrap().program, [0x00, 0xE0, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn octo_source_is_rejected() {
+        let gif = cartridge(&with_length(
+            r#"{"program": ": main\n  clear\n  loop again", "options": {}}"#,
+        ));
+        let error = decode(&gif).err().unwrap();
+        assert!(error.starts_with("cartridge holds Octo source (':')"));
+    }
+
+    #[test]
+    fn saved_source_cartridges_do_not_load() {
+        let path = fixture_path("source.gif");
+        let error = Rom::load(&path, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}: cartridge holds Octo source (':') and only pre-assembled cartridges are \
+                 supported, export it from Octo as a .ch8 first",
+                path.display()
+            )
+        );
+    }
+
+    #[test]
+    fn short_payloads_are_rejected() {
+        let mut payload = with_length(r#"{"program": [0, 224]}"#);
+        payload[3] += 100;
+        let error = decode(&cartridge(&payload)).err().unwrap();
+        assert!(error.contains("holds fewer"), "{}", error);
+    }
+}
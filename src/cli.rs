@@ -0,0 +1,528 @@
+//! Command line parsing. Every subcommand takes one positional argument, the
+//! rom (or source file for `asm`), and `chip8 rom.ch8` is short for
+//! `chip8 run rom.ch8`. Running a directory, or nothing at all, opens the rom
+//! browser instead.
+
+use chip8::audio::Tone;
+use chip8::blocks::Engine;
+use chip8::keymap::Keymap;
+use chip8::quirks::QuirkPreset;
+use chip8::render::{Palette, RenderMode};
+use chip8::rom::Platform;
+use chip8::sys::Sys;
+use chip8::timing::Timing;
+use std::path::PathBuf;
+
+pub const HELP: &str = "\
+chip8 - a CHIP-8, SUPER-CHIP and XO-CHIP interpreter for the terminal
+
+Usage: chip8 <command> <file> [options]
+       chip8 <rom> [options]       same as chip8 run <rom>
+       chip8 [directory] [options] pick a rom from a list
+
+Commands:
+  run      play a rom in the terminal, or pick one from a directory
+  disasm   print a rom as assembly
+  asm      assemble a source file into a rom
+  info     show what is known about a rom
+  trace    print every executed instruction and the registers after it
+  bench    run a rom as fast as possible and report its speed
+
+Run 'chip8 <command> --help' to see the options of a command.
+";
+
+const RUN_HELP: &str = "\
+Usage: chip8 run <rom.ch8|cartridge.gif|directory> [options]
+
+A directory (the current one if none is given) opens a list of its roms.
+Type to filter it, pick one with the arrow keys and enter, and quitting the
+rom brings the list back.
+
+Only pre-assembled Octo cartridge GIFs are supported. Most cartridges Octo
+saves hold Octo source, which is rejected: export those from Octo as a .ch8
+first.
+
+Machine:
+  --platform <chip8|schip|xo-chip>  memory size and rom size limit
+  --quirks <vip|schip|xo-chip>      quirk preset
+  --ipf <n>                         instructions per frame
+  --timing <ipf|vip>                vip runs as fast as the COSMAC VIP did
+                                    instead of a fixed ipf (default ipf)
+  --keymap <16 keys>                keys pressing chip8 keys 0 to F
+  --seed <n>                        seed for the random number generator
+  --engine <interpreter|blocks>     how instructions run (default interpreter)
+  --sys <ignore|error>              what 0NNN machine code calls do (default
+                                    ignore)
+
+Display:
+  --render <text|sixel|kitty>       how the screen is drawn (default text)
+  --theme <name|rrggbb,rrggbb>      mono, amber, green, lcd or custom colors
+  --no-hud                          hide the status line
+  --frames <n>                      run n frames headless, then print the screen
+  --record <out.gif>                record the screen to an animated GIF
+
+Audio:
+  --mute                            no sound at all
+  --wav <out.wav>                   write the sound to a file instead
+  --frequency <hz>                  beep pitch (default 440)
+  --volume <0-1>                    beep volume (default 0.25)
+  --waveform <square|triangle|sawtooth|sine>
+
+Hotkeys:
+  esc, q                            quit
+  space                             pause
+  .                                 run one frame while paused
+  + / -                             faster / slower
+  backspace                         back to normal speed
+  f5 / f9                           save / load a state
+
+Settings that aren't given come from the config file's section for the rom,
+the cartridge, the rom database, then the config file's defaults. The config
+file is $XDG_CONFIG_HOME/chip8/config.toml and can change hotkeys too.
+";
+
+const DISASM_HELP: &str = "\
+Usage: chip8 disasm <rom.ch8|cartridge.gif> [options]
+
+Prints the program as assembly that 'chip8 asm' turns back into the same rom.
+
+  --platform <chip8|schip|xo-chip>  platform whose size limit applies
+";
+
+const ASM_HELP: &str = "\
+Usage: chip8 asm <source.asm> [options]
+
+Assembles Cowgod style mnemonics (LD V0, 0x12), labels (loop:), DB bytes
+and ; comments into a rom.
+
+  -o, --output <rom.ch8>            where to write the rom (default: the
+                                    source with a .ch8 extension)
+";
+
+const INFO_HELP: &str = "\
+Usage: chip8 info <rom.ch8|cartridge.gif> [options]
+
+Shows the rom's hashes, what the database knows about it and the settings
+it would run with.
+
+  --platform <chip8|schip|xo-chip>  platform whose size limit applies
+";
+
+const TRACE_HELP: &str = "\
+Usage: chip8 trace <rom.ch8|cartridge.gif> [options]
+
+Runs the rom headless and prints each instruction with the registers and the
+call stack after it, until it runs the instructions or the program fails.
+
+  --instructions <n>                how many to run (default 1000, K/M/G allowed)
+  --platform <chip8|schip|xo-chip>  memory size and rom size limit
+  --quirks <vip|schip|xo-chip>      quirk preset
+  --ipf <n>                         instructions per frame, for the timers
+  --keymap <16 keys>                keys pressing chip8 keys 0 to F
+  --seed <n>                        seed for the random number generator
+  --sys <ignore|error>              what 0NNN machine code calls do (default
+                                    ignore)
+";
+
+const BENCH_HELP: &str = "\
+Usage: chip8 bench <rom.ch8|cartridge.gif> [options]
+
+Runs the rom headless without any frame limit and reports instructions per
+second.
+
+  --instructions <n>                how many to run (default 100M, K/M/G allowed)
+  --platform <chip8|schip|xo-chip>  memory size and rom size limit
+  --quirks <vip|schip|xo-chip>      quirk preset
+  --ipf <n>                         instructions per frame, for the timers
+  --timing <ipf|vip>                frames of ipf instructions or of VIP cycles
+  --seed <n>                        seed for the random number generator
+  --engine <interpreter|blocks>     how instructions run (default interpreter)
+";
+
+struct Subcommand {
+    name: &'static str,
+    help: &'static str,
+    options: &'static [&'static str],
+}
+
+const SUBCOMMANDS: [Subcommand; 6] = [
+    Subcommand {
+        name: "run",
+        help: RUN_HELP,
+        options: &[
+            "--platform",
+            "--quirks",
+            "--ipf",
+            "--timing",
+            "--keymap",
+            "--seed",
+            "--engine",
+            "--sys",
+            "--render",
+            "--theme",
+            "--colors",
+            "--no-hud",
+            "--frames",
+            "--record",
+            "--mute",
+            "--wav",
+            "--frequency",
+            "--volume",
+            "--waveform",
+        ],
+    },
+    Subcommand {
+        name: "disasm",
+        help: DISASM_HELP,
+        options: &["--platform"],
+    },
+    Subcommand {
+        name: "asm",
+        help: ASM_HELP,
+        options: &["--output", "-o"],
+    },
+    Subcommand {
+        name: "info",
+        help: INFO_HELP,
+        options: &["--platform"],
+    },
+    Subcommand {
+        name: "trace",
+        help: TRACE_HELP,
+        options: &[
+            "--instructions",
+            "--platform",
+            "--quirks",
+            "--ipf",
+            "--keymap",
+            "--seed",
+            "--sys",
+        ],
+    },
+    Subcommand {
+        name: "bench",
+        help: BENCH_HELP,
+        options: &[
+            "--instructions",
+            "--platform",
+            "--quirks",
+            "--ipf",
+            "--timing",
+            "--seed",
+            "--engine",
+        ],
+    },
+];
+
+pub enum Command {
+    Run(Options),
+    /// `run` given a directory.
+    Browse(Options),
+    Disasm(Options),
+    Asm(Options),
+    Info(Options),
+    Trace(Options),
+    Bench(Options),
+    Help(&'static str),
+}
+
+/// Everything that can be given on the command line. Each subcommand only
+/// accepts the options that mean something to it.
+#[derive(Default)]
+pub struct Options {
+    /// The rom, or the source file for `asm`.
+    pub file: PathBuf,
+    pub platform: Option<Platform>,
+    pub quirks: Option<QuirkPreset>,
+    pub ipf: Option<u32>,
+    pub timing: Option<Timing>,
+    pub keymap: Option<Keymap>,
+    pub seed: Option<u64>,
+    pub engine: Option<Engine>,
+    pub sys: Option<Sys>,
+    pub render: Option<RenderMode>,
+    pub palette: Option<Palette>,
+    pub no_hud: bool,
+    pub frames: Option<u64>,
+    pub record: Option<PathBuf>,
+    pub mute: bool,
+    pub wav: Option<PathBuf>,
+    pub tone: Tone,
+    pub instructions: Option<u64>,
+    pub output: Option<PathBuf>,
+}
+
+/// Parses the arguments after the program name.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let Some(first) = args.first() else {
+        return Ok(Command::Browse(Options {
+            file: PathBuf::from("."),
+            ..Options::default()
+        }));
+    };
+    let (subcommand, rest) = match first.as_str() {
+        "-h" | "--help" => return Ok(Command::Help(HELP)),
+        "help" => {
+            return match args.get(1) {
+                Some(name) => find(name)
+                    .map(|subcommand| Command::Help(subcommand.help))
+                    .ok_or_else(|| format!("unknown command '{}'\n\n{}", name, HELP)),
+                None => Ok(Command::Help(HELP)),
+            }
+        }
+        name => match find(name) {
+            Some(subcommand) => (subcommand, &args[1..]),
+            None if name.starts_with('-') => {
+                return Err(format!(
+                    "expected a command or a rom before '{}'\n\n{}",
+                    name, HELP
+                ))
+            }
+            None => (find("run").unwrap(), args),
+        },
+    };
+    parse_subcommand(subcommand, rest).map_err(|error| {
+        format!(
+            "{}\nRun 'chip8 {} --help' to see its options.",
+            error, subcommand.name
+        )
+    })
+}
+
+fn find(name: &str) -> Option<&'static Subcommand> {
+    SUBCOMMANDS
+        .iter()
+        .find(|subcommand| subcommand.name == name)
+}
+
+fn parse_subcommand(subcommand: &Subcommand, args: &[String]) -> Result<Command, String> {
+    let mut options = Options::default();
+    let mut file = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help(subcommand.help));
+        }
+        if !arg.starts_with('-') {
+            if file.is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            file = Some(PathBuf::from(arg));
+            continue;
+        }
+        if !subcommand.options.contains(&arg.as_str()) {
+            let known = SUBCOMMANDS
+                .iter()
+                .any(|subcommand| subcommand.options.contains(&arg.as_str()));
+            return Err(if known {
+                format!("{} doesn't take {}", subcommand.name, arg)
+            } else {
+                format!("unknown option '{}'", arg)
+            });
+        }
+        match arg.as_str() {
+            "--mute" => options.mute = true,
+            "--no-hud" => options.no_hud = true,
+            _ => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                options.set(arg, value)?;
+            }
+        }
+    }
+    options.file = match (file, subcommand.name) {
+        (Some(file), _) => file,
+        (None, "run") => PathBuf::from("."),
+        (None, "asm") => return Err("no source file given".to_string()),
+        (None, _) => return Err("no rom given".to_string()),
+    };
+    Ok(match subcommand.name {
+        "run" if options.file.is_dir() => {
+            if options.frames.is_some() {
+                return Err("--frames needs a rom, not a directory".to_string());
+            }
+            Command::Browse(options)
+        }
+        "run" => Command::Run(options),
+        "disasm" => Command::Disasm(options),
+        "asm" => Command::Asm(options),
+        "info" => Command::Info(options),
+        "trace" => Command::Trace(options),
+        _ => Command::Bench(options),
+    })
+}
+
+impl Options {
+    fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "--platform" => self.platform = Some(value.parse()?),
+            "--quirks" => self.quirks = Some(value.parse()?),
+            "--ipf" => {
+                self.ipf = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|ipf| *ipf > 0)
+                        .ok_or_else(|| format!("invalid instructions per frame '{}'", value))?,
+                )
+            }
+            "--timing" => self.timing = Some(value.parse()?),
+            "--keymap" => self.keymap = Some(value.parse()?),
+            "--seed" => {
+                self.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("seed '{}' should be a whole number", value))?,
+                )
+            }
+            "--engine" => self.engine = Some(value.parse()?),
+            "--sys" => self.sys = Some(value.parse()?),
+            "--render" => self.render = Some(value.parse()?),
+            "--theme" | "--colors" => self.palette = Some(value.parse()?),
+            "--frames" => self.frames = Some(count(value)?),
+            "--record" => {
+                if !value.to_ascii_lowercase().ends_with(".gif") {
+                    return Err(format!(
+                        "recordings are GIFs, '{}' should end in .gif",
+                        value
+                    ));
+                }
+                self.record = Some(PathBuf::from(value))
+            }
+            "--wav" => self.wav = Some(PathBuf::from(value)),
+            "--frequency" => {
+                self.tone.frequency = value
+                    .parse()
+                    .ok()
+                    .filter(|frequency: &f32| *frequency > 0.0)
+                    .ok_or_else(|| format!("invalid frequency '{}'", value))?
+            }
+            "--volume" => {
+                self.tone.volume = value
+                    .parse()
+                    .ok()
+                    .filter(|volume| (0.0..=1.0).contains(volume))
+                    .ok_or_else(|| format!("volume '{}' should be between 0 and 1", value))?
+            }
+            "--waveform" => self.tone.waveform = value.parse()?,
+            "--instructions" => self.instructions = Some(count(value)?),
+            "--output" | "-o" => self.output = Some(PathBuf::from(value)),
+            _ => unreachable!("{} is listed as an option but not handled", option),
+        }
+        Ok(())
+    }
+}
+
+/// A positive count, optionally ending in K, M or G for thousands, millions
+/// and billions.
+fn count(value: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match value.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&value[..value.len() - 1], 1_000),
+        Some('M') => (&value[..value.len() - 1], 1_000_000),
+        Some('G') => (&value[..value.len() - 1], 1_000_000_000),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|count| count.checked_mul(multiplier))
+        .filter(|count| *count > 0)
+        .ok_or_else(|| {
+            format!(
+                "'{}' should be a positive number like 500, 20K or 100M",
+                value
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Command, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse(&args)
+    }
+
+    fn parsed(args: &[&str]) -> Options {
+        match parse_args(args) {
+            Ok(Command::Run(options)) | Ok(Command::Trace(options)) => options,
+            Ok(_) => panic!("{:?} isn't run or trace", args),
+            Err(error) => panic!("{:?}: {}", args, error),
+        }
+    }
+
+    /// The first line of the error, without the hint to see `--help`.
+    fn error(args: &[&str]) -> String {
+        match parse_args(args) {
+            Ok(_) => panic!("{:?} parsed", args),
+            Err(error) => error.lines().next().unwrap().to_string(),
+        }
+    }
+
+    #[test]
+    fn a_rom_alone_runs_it() {
+        let options = parsed(&["game.ch8", "--ipf", "20", "--mute"]);
+        assert_eq!(options.file, PathBuf::from("game.ch8"));
+        assert_eq!(options.ipf, Some(20));
+        assert!(options.mute);
+    }
+
+    #[test]
+    fn counts_take_suffixes() {
+        let options = parsed(&["trace", "game.ch8", "--instructions", "20K"]);
+        assert_eq!(options.instructions, Some(20_000));
+        let options = parsed(&["trace", "game.ch8", "--instructions", "3m"]);
+        assert_eq!(options.instructions, Some(3_000_000));
+    }
+
+    #[test]
+    fn unknown_options_are_errors() {
+        assert_eq!(error(&["game.ch8", "--nope"]), "unknown option '--nope'");
+        assert_eq!(
+            error(&["disasm", "game.ch8", "--ipf", "10"]),
+            "disasm doesn't take --ipf"
+        );
+        assert!(error(&["--nope"]).starts_with("expected a command or a rom before '--nope'"));
+    }
+
+    #[test]
+    fn options_need_their_value() {
+        assert_eq!(error(&["game.ch8", "--ipf"]), "--ipf needs a value");
+        assert_eq!(error(&["asm", "game.asm", "-o"]), "-o needs a value");
+    }
+
+    #[test]
+    fn bad_numbers_are_errors() {
+        assert_eq!(
+            error(&["game.ch8", "--ipf", "fast"]),
+            "invalid instructions per frame 'fast'"
+        );
+        assert_eq!(
+            error(&["game.ch8", "--ipf", "0"]),
+            "invalid instructions per frame '0'"
+        );
+        assert_eq!(
+            error(&["game.ch8", "--seed", "-1"]),
+            "seed '-1' should be a whole number"
+        );
+        assert_eq!(
+            error(&["bench", "game.ch8", "--instructions", "5X"]),
+            "'5X' should be a positive number like 500, 20K or 100M"
+        );
+        assert_eq!(
+            error(&["game.ch8", "--volume", "2"]),
+            "volume '2' should be between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_subcommands_help() {
+        let error = parse_args(&["info"]).err().unwrap();
+        assert_eq!(
+            error,
+            "no rom given\nRun 'chip8 info --help' to see its options."
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! The user's config file, `$XDG_CONFIG_HOME/chip8/config.toml` (usually
+//! `~/.config/chip8/config.toml`):
+//!
+//! ```toml
+//! ipf = 12
+//! quirks = "vip"
+//! keymap = "x123qweasdzc4rfv"
+//! theme = "amber"
+//! render = "text"
+//! save_dir = "~/chip8/saves"
+//!
+//! [hotkeys]
+//! pause = "p"
+//! quit = ["esc", "q"]
+//!
+//! # by file name
+//! [rom."tetris.rom"]
+//! ipf = 8
+//!
+//! # or by SHA-1
+//! [rom.5f518084744bf3cb8733f6e5454dfd1634320563]
+//! theme = "green"
+//! ```
+//!
+//! Top level settings are defaults, so a cartridge or the rom database still
+//! wins over them. Settings in a `[rom]` section win over everything but the
+//! command line.
+
+use chip8::hotkeys::{self, Hotkeys, ACTIONS};
+use chip8::keymap::Keymap;
+use chip8::quirks::QuirkPreset;
+use chip8::render::{Palette, RenderMode};
+use chip8::rom::Rom;
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+use toml::de::{DeString, DeTable, DeValue};
+use toml::Spanned;
+
+/// Settings that can be given for every rom or for a single one.
+#[derive(Clone, Copy, Default)]
+pub struct Overrides {
+    pub ipf: Option<u32>,
+    pub quirks: Option<QuirkPreset>,
+    pub keymap: Option<Keymap>,
+    pub palette: Option<Palette>,
+    pub render: Option<RenderMode>,
+}
+
+impl Overrides {
+    /// Takes whatever `self` leaves unset from `fallback`.
+    fn or(self, fallback: Overrides) -> Overrides {
+        Overrides {
+            ipf: self.ipf.or(fallback.ipf),
+            quirks: self.quirks.or(fallback.quirks),
+            keymap: self.keymap.or(fallback.keymap),
+            palette: self.palette.or(fallback.palette),
+            render: self.render.or(fallback.render),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Config {
+    pub defaults: Overrides,
+    pub hotkeys: Hotkeys,
+    save_dir: Option<PathBuf>,
+    /// `[rom]` sections keyed by file name or SHA-1.
+    roms: Vec<(String, Overrides)>,
+}
+
+/// A problem with the config file, at the byte range it was found in.
+struct Error {
+    span: Range<usize>,
+    message: String,
+}
+
+fn error<T>(span: Range<usize>, message: impl Into<String>) -> Result<T, Error> {
+    Err(Error {
+        span,
+        message: message.into(),
+    })
+}
+
+impl Config {
+    /// Reads the config file. A missing file is the same as an empty one.
+    pub fn load() -> Result<Config, String> {
+        let Some(path) = path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                Config::parse(&text).map_err(|error| format!("{}:{}", path.display(), error))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(error) => Err(format!("couldn't read {}: {}", path.display(), error)),
+        }
+    }
+
+    /// Errors start with the line they were found on.
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let document = DeTable::parse(text).map_err(|error| {
+            let line = error.span().map_or(1, |span| line(text, span.start));
+            format!("{}: {}", line, error.message())
+        })?;
+        Config::from_table(document.get_ref())
+            .map_err(|error| format!("{}: {}", line(text, error.span.start), error.message))
+    }
+
+    fn from_table(table: &DeTable) -> Result<Config, Error> {
+        let mut config = Config::default();
+        for (key, value) in table {
+            match key.get_ref().as_ref() {
+                "save_dir" => config.save_dir = Some(expand_home(string(value)?)),
+                "hotkeys" => {
+                    for (key, value) in self::table(value)? {
+                        let Some(action) =
+                            ACTIONS.iter().find(|action| action.name() == key.get_ref())
+                        else {
+                            let names: Vec<&str> =
+                                ACTIONS.iter().map(|action| action.name()).collect();
+                            return error(
+                                key.span(),
+                                format!(
+                                    "unknown hotkey '{}', expected one of {}",
+                                    key.get_ref(),
+                                    names.join(", ")
+                                ),
+                            );
+                        };
+                        config.hotkeys.bind(*action, &keys(value)?);
+                    }
+                }
+                "rom" => {
+                    for (key, value) in self::table(value)? {
+                        let mut overrides = Overrides::default();
+                        for (key, value) in self::table(value)? {
+                            set(&mut overrides, key, value)?;
+                        }
+                        config.roms.push((key.get_ref().to_string(), overrides));
+                    }
+                }
+                _ => set(&mut config.defaults, key, value)?,
+            }
+        }
+        Ok(config)
+    }
+
+    /// The settings from the `[rom]` sections for `rom`. A section for the
+    /// SHA-1 wins over one for the file name.
+    pub fn rom(&self, rom: &Rom) -> Overrides {
+        let file_name = rom
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let section = |matches: &dyn Fn(&str) -> bool| {
+            self.roms
+                .iter()
+                .find(|(key, _)| matches(key))
+                .map(|(_, overrides)| *overrides)
+                .unwrap_or_default()
+        };
+        let by_hash = section(&|key| key.eq_ignore_ascii_case(&rom.sha1));
+        let by_name = section(&|key| Some(key) == file_name.as_deref());
+        by_hash.or(by_name)
+    }
+
+    /// Where save states go, `$XDG_DATA_HOME/chip8/saves` unless the config
+    /// says otherwise.
+    pub fn save_dir(&self) -> PathBuf {
+        if let Some(dir) = &self.save_dir {
+            return dir.clone();
+        }
+        let data = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_default();
+        data.join("chip8").join("saves")
+    }
+}
+
+fn path() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("chip8").join("config.toml"))
+}
+
+fn line(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn set(
+    overrides: &mut Overrides,
+    key: &Spanned<DeString>,
+    value: &Spanned<DeValue>,
+) -> Result<(), Error> {
+    match key.get_ref().as_ref() {
+        "ipf" => {
+            let ipf = match value.get_ref() {
+                DeValue::Integer(ipf) => u32::from_str_radix(ipf.as_str(), ipf.radix()).ok(),
+                _ => None,
+            };
+            overrides.ipf = Some(ipf.filter(|ipf| *ipf > 0).map_or_else(
+                || error(value.span(), "ipf should be a positive number"),
+                Ok,
+            )?);
+        }
+        "quirks" => overrides.quirks = Some(parsed(value)?),
+        "keymap" => overrides.keymap = Some(parsed(value)?),
+        "theme" => overrides.palette = Some(parsed(value)?),
+        "render" => overrides.render = Some(parsed(value)?),
+        other => return error(key.span(), format!("unknown setting '{}'", other)),
+    }
+    Ok(())
+}
+
+fn string<'a>(value: &'a Spanned<DeValue>) -> Result<&'a str, Error> {
+    match value.get_ref().as_str() {
+        Some(string) => Ok(string),
+        None => error(value.span(), "expected a string"),
+    }
+}
+
+fn table<'a, 'i>(value: &'a Spanned<DeValue<'i>>) -> Result<&'a DeTable<'i>, Error> {
+    match value.get_ref().as_table() {
+        Some(table) => Ok(table),
+        None => error(value.span(), "expected a table"),
+    }
+}
+
+fn parsed<T: FromStr<Err = String>>(value: &Spanned<DeValue>) -> Result<T, Error> {
+    string(value)?
+        .parse()
+        .or_else(|message| error(value.span(), message))
+}
+
+/// A key name or a list of them.
+fn keys(value: &Spanned<DeValue>) -> Result<Vec<crossterm::event::KeyCode>, Error> {
+    let names = match value.get_ref().as_array() {
+        Some(array) => array.iter().collect(),
+        None => vec![value],
+    };
+    names
+        .into_iter()
+        .map(|name| {
+            hotkeys::parse_key(string(name)?).or_else(|message| error(name.span(), message))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_name_the_line() {
+        let error = Config::parse("ipf = 10\n\n[rom.\"pong.ch8\"]\nkeymap = \"abc\"\n")
+            .err()
+            .unwrap();
+        assert_eq!(error, "4: keymap 'abc' needs exactly 16 characters");
+        let error = Config::parse("theme = \"mono\"\nspeed = 3\n")
+            .err()
+            .unwrap();
+        assert_eq!(error, "2: unknown setting 'speed'");
+    }
+
+    #[test]
+    fn hash_sections_win_over_file_names() {
+        let rom = Rom::from_bytes("roms/pong.ch8".into(), vec![0x12, 0x00], None).unwrap();
+        let text = format!(
+            "ipf = 5\n[rom.\"pong.ch8\"]\nipf = 7\nquirks = \"schip\"\n[rom.{}]\nipf = 9\n",
+            rom.sha1
+        );
+        let config = Config::parse(&text).unwrap();
+        let overrides = config.rom(&rom);
+        assert_eq!(overrides.ipf, Some(9));
+        assert_eq!(overrides.quirks, Some(QuirkPreset::Schip));
+        assert_eq!(config.defaults.ipf, Some(5));
+    }
+}
@@ -0,0 +1,203 @@
+//! Known programs, keyed by the SHA-1 of the rom, in the spirit of the
+//! CHIP-8 community database (https://github.com/chip-8/chip-8-database).
+
+use crate::keymap::{Keymap, QWERTY_KEYMAP};
+use crate::quirks::QuirkPreset;
+use crate::render::Palette;
+use crate::rom::Platform;
+
+pub struct Entry {
+    pub sha1: &'static str,
+    pub title: &'static str,
+    pub author: &'static str,
+    pub platform: Platform,
+    pub quirks: QuirkPreset,
+    /// Instructions per frame the program was meant to run at.
+    pub ipf: u32,
+    pub keymap: Option<Keymap>,
+    pub palette: Option<Palette>,
+}
+
+pub const ENTRIES: &[Entry] = &[
+    Entry {
+        sha1: "1ba58656810b67fd131eb9af3e3987863bf26c90",
+        title: "IBM Logo",
+        author: "Unknown",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "f9ad6ba27ce0efd1d2a0e5d25b732796c8afeb6f",
+        title: "Chip-8 Test Rom",
+        author: "Tronix",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "ce67825744c8044d29b538fd1321ee019d6d7c01",
+        title: "CHIP-8 splash screen",
+        author: "Timendus",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "b2dacf6d85785d6c2315ce449912c8a8a5954e2e",
+        title: "Corax+ opcode test",
+        author: "Timendus, after Corax89",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "55a6716dacc2f93dce3d39fb8d231083016a1cc0",
+        title: "Flags test",
+        author: "Timendus",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "e2149cb836131a142ca7e2dc2f2283381ae5faaa",
+        title: "Quirks test",
+        author: "Timendus",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "455b9fc69cc06e2b5b72f7d1ac5f6c86ac349e77",
+        title: "Keypad test",
+        author: "Timendus",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "b119651b5aa08557a85ca2ad5de3d1a86796b66b",
+        title: "Beep test",
+        author: "Timendus",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "477b3e09c43839ea5478b4f0e24536edab594f89",
+        title: "Scrolling test",
+        author: "Timendus",
+        platform: Platform::Schip,
+        quirks: QuirkPreset::Schip,
+        ipf: 30,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "9df1689015a0d1d95144f141903296f9f1c35fc5",
+        title: "BC_test",
+        author: "BestCoder",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Schip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "f1cfcffe1937ed6dd6eeed1a7f85dfc777bda700",
+        title: "Opcode test",
+        author: "Corax89",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 15,
+        keymap: None,
+        palette: None,
+    },
+    Entry {
+        sha1: "fca71182a8838b686573e69b22aff945d79fe1d0",
+        title: "Airplane",
+        author: "Unknown",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 10,
+        keymap: Some(QWERTY_KEYMAP),
+        palette: None,
+    },
+    Entry {
+        sha1: "5f518084744bf3cb8733f6e5454dfd1634320563",
+        title: "Tetris",
+        author: "Fran Dachille",
+        platform: Platform::Chip8,
+        quirks: QuirkPreset::Vip,
+        ipf: 10,
+        keymap: Some(QWERTY_KEYMAP),
+        palette: None,
+    },
+];
+
+pub fn lookup(sha1: &str) -> Option<&'static Entry> {
+    ENTRIES.iter().find(|entry| entry.sha1 == sha1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rom::Rom;
+    use std::path::Path;
+
+    #[test]
+    fn bundled_roms_are_found_by_their_hash() {
+        let bytes =
+            std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("BC_test.ch8")).unwrap();
+        let sha1 = sha1_smol::Sha1::from(&bytes).digest().to_string();
+        assert_eq!(sha1, "9df1689015a0d1d95144f141903296f9f1c35fc5");
+        let entry = lookup(&sha1).unwrap();
+        assert_eq!(entry.title, "BC_test");
+        assert_eq!(entry.platform, Platform::Chip8);
+        assert_eq!(entry.quirks, QuirkPreset::Schip);
+        assert_eq!(entry.quirks.quirks(), QuirkPreset::Schip.quirks());
+        assert_eq!(entry.ipf, 15);
+    }
+
+    #[test]
+    fn loading_a_rom_applies_its_entry() {
+        let rom = Rom::load(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("8-scrolling.ch8"),
+            None,
+        )
+        .unwrap();
+        let entry = rom.entry.unwrap();
+        assert_eq!(entry.title, "Scrolling test");
+        assert_eq!(rom.platform, Platform::Schip);
+        assert_eq!(rom.name(), "Scrolling test");
+    }
+
+    #[test]
+    fn keymaps_read_back_as_themselves() {
+        for keymap in ENTRIES.iter().filter_map(|entry| entry.keymap) {
+            assert_eq!(keymap.to_string().parse::<Keymap>(), Ok(keymap));
+        }
+    }
+
+    #[test]
+    fn unknown_hashes_have_no_entry() {
+        assert!(lookup("0000000000000000000000000000000000000000").is_none());
+        assert!(lookup("").is_none());
+    }
+}
@@ -0,0 +1,109 @@
+//! What the emulator needs from whatever it runs in: somewhere to show
+//! frames, key presses, somewhere for the sound to go and the time. The
+//! terminal implementations live in `tty`, `Beeper` is the audio one, and
+//! the headless ones here run the same engine without a terminal.
+
+use crate::audio::Pattern;
+use crate::hotkeys::Action;
+use crate::render::Screen;
+use crate::scheduler::Scheduler;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shows frames and the status around them.
+pub trait Display {
+    /// Called at most 60 times a second, with the quirk profile's name.
+    fn draw(&mut self, screen: &Screen, scheduler: &Scheduler, quirks: &str) -> io::Result<()>;
+    /// A short note for the user, e.g. that a state got saved.
+    fn message(&mut self, text: String);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    /// A chip8 key, 0 to F.
+    Key(u8),
+    Action(Action),
+}
+
+pub trait Input {
+    /// Everything that happened since the last call, without blocking.
+    /// Called once per frame, paused or not.
+    fn poll(&mut self) -> io::Result<Vec<InputEvent>>;
+}
+
+pub trait Audio {
+    /// Called once per emulated frame at normal speed.
+    fn tick(&mut self, sound_on: bool, pattern: Option<Pattern>) -> io::Result<()>;
+}
+
+pub trait Clock {
+    fn now(&self) -> Instant;
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+    fn sleep(&mut self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A clock that only moves when slept on, so headless runs go as fast as
+/// they can while the emulated machine still sees 60 frames a second.
+pub struct FakeClock(Instant);
+
+impl Default for FakeClock {
+    fn default() -> FakeClock {
+        FakeClock(Instant::now())
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.0
+    }
+    fn sleep(&mut self, duration: Duration) {
+        self.0 += duration;
+    }
+}
+
+/// Throws frames and messages away.
+pub struct HeadlessDisplay;
+
+impl Display for HeadlessDisplay {
+    fn draw(&mut self, _: &Screen, _: &Scheduler, _: &str) -> io::Result<()> {
+        Ok(())
+    }
+    fn message(&mut self, _: String) {}
+}
+
+/// Input given up front as events and the frames they happen on.
+pub struct ScriptedInput {
+    events: Vec<(u64, InputEvent)>,
+    frame: u64,
+}
+
+impl ScriptedInput {
+    pub fn new(events: Vec<(u64, InputEvent)>) -> ScriptedInput {
+        ScriptedInput { events, frame: 0 }
+    }
+}
+
+impl Input for ScriptedInput {
+    fn poll(&mut self) -> io::Result<Vec<InputEvent>> {
+        let frame = self.frame;
+        self.frame += 1;
+        Ok(self
+            .events
+            .iter()
+            .filter(|(at, _)| *at == frame)
+            .map(|(_, event)| *event)
+            .collect())
+    }
+}
@@ -0,0 +1,107 @@
+use crossterm::event::KeyCode;
+
+/// Things the emulator does when a hotkey is pressed, as opposed to keys
+/// that reach the chip8.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Quit,
+    Pause,
+    /// Runs a single frame while paused.
+    Advance,
+    Faster,
+    Slower,
+    NormalSpeed,
+    SaveState,
+    LoadState,
+}
+
+pub const ACTIONS: [Action; 8] = [
+    Action::Quit,
+    Action::Pause,
+    Action::Advance,
+    Action::Faster,
+    Action::Slower,
+    Action::NormalSpeed,
+    Action::SaveState,
+    Action::LoadState,
+];
+
+impl Action {
+    /// The name used for the action in the config file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Pause => "pause",
+            Action::Advance => "advance",
+            Action::Faster => "faster",
+            Action::Slower => "slower",
+            Action::NormalSpeed => "normal_speed",
+            Action::SaveState => "save_state",
+            Action::LoadState => "load_state",
+        }
+    }
+}
+
+pub struct Hotkeys(Vec<(KeyCode, Action)>);
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys(vec![
+            (KeyCode::Esc, Action::Quit),
+            (KeyCode::Char('q'), Action::Quit),
+            (KeyCode::Char(' '), Action::Pause),
+            (KeyCode::Char('.'), Action::Advance),
+            (KeyCode::Char('+'), Action::Faster),
+            (KeyCode::Char('-'), Action::Slower),
+            (KeyCode::Backspace, Action::NormalSpeed),
+            (KeyCode::F(5), Action::SaveState),
+            (KeyCode::F(9), Action::LoadState),
+        ])
+    }
+}
+
+impl Hotkeys {
+    /// Makes `keys` the only keys for `action`.
+    pub fn bind(&mut self, action: Action, keys: &[KeyCode]) {
+        self.0.retain(|(_, bound)| *bound != action);
+        self.0.extend(keys.iter().map(|key| (*key, action)));
+    }
+
+    /// Letters are matched case-insensitively.
+    pub fn action(&self, code: KeyCode) -> Option<Action> {
+        let code = match code {
+            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+            code => code,
+        };
+        self.0
+            .iter()
+            .find(|(key, _)| *key == code)
+            .map(|(_, action)| *action)
+    }
+}
+
+/// Parses a key name from the config file: a single character, or one of
+/// space, esc, backspace, enter, tab, up, down, left, right and f1 to f12.
+pub fn parse_key(name: &str) -> Result<KeyCode, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c.to_ascii_lowercase()));
+    }
+    let lower = name.to_ascii_lowercase();
+    let key = match lower.as_str() {
+        "space" => KeyCode::Char(' '),
+        "esc" | "escape" => KeyCode::Esc,
+        "backspace" => KeyCode::Backspace,
+        "enter" | "return" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        _ => match lower.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n @ 1..=12) => KeyCode::F(n),
+            _ => return Err(format!("unknown key '{}'", name)),
+        },
+    };
+    Ok(key)
+}
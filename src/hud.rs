@@ -0,0 +1,76 @@
+use chip8::scheduler::Scheduler;
+use crossterm::{
+    cursor,
+    style::Print,
+    terminal::{Clear, ClearType},
+    QueueableCommand,
+};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+/// Status line drawn under the display: rom name, quirk profile, measured
+/// speed and short-lived messages.
+pub struct Hud {
+    pub visible: bool,
+    rom_name: String,
+    messages: Vec<(String, Instant)>,
+    last_line: String,
+}
+
+impl Hud {
+    pub fn new(rom_name: String, visible: bool) -> Self {
+        Self {
+            visible,
+            rom_name,
+            messages: Vec::new(),
+            last_line: String::new(),
+        }
+    }
+
+    /// Shows `text` at the end of the status line for a few seconds.
+    pub fn message(&mut self, text: impl Into<String>) {
+        self.messages.push((text.into(), Instant::now()));
+    }
+
+    pub fn line(&mut self, scheduler: &Scheduler, quirks: &str) -> String {
+        let mut line = format!(
+            "{} | {} | {:.0} fps | {:.0} ips | {}",
+            self.rom_name,
+            quirks,
+            scheduler.fps,
+            scheduler.ips,
+            scheduler.status()
+        );
+        self.messages
+            .retain(|(_, shown_at)| shown_at.elapsed() < MESSAGE_DURATION);
+        for (text, _) in &self.messages {
+            line = format!("{} | {}", line, text);
+        }
+        line
+    }
+
+    /// Draws the status line on `row`, which should be the first row below
+    /// the game area. Nothing is written while the line is unchanged.
+    pub fn draw<W: Write>(
+        &mut self,
+        out: &mut W,
+        row: u16,
+        scheduler: &Scheduler,
+        quirks: &str,
+    ) -> io::Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        let line = self.line(scheduler, quirks);
+        if line == self.last_line {
+            return Ok(());
+        }
+        out.queue(cursor::MoveTo(0, row))?
+            .queue(Clear(ClearType::CurrentLine))?
+            .queue(Print(&line))?;
+        self.last_line = line;
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Which keyboard character presses each of the 16 chip8 keys.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keymap([char; 16]);
+
+/// Every key is typed as its own hex digit.
+pub const HEX_KEYMAP: Keymap = Keymap([
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
+]);
+/// The usual 4x4 block on the left of a QWERTY keyboard.
+pub const QWERTY_KEYMAP: Keymap = Keymap([
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+]);
+
+impl Keymap {
+    /// Characters are matched case-insensitively.
+    pub fn key(&self, c: char) -> Option<u8> {
+        let c = c.to_ascii_lowercase();
+        self.0.iter().position(|k| *k == c).map(|key| key as u8)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        HEX_KEYMAP
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.iter().collect::<String>())
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = String;
+    /// A keymap is written as 16 characters, the first presses key 0 and the
+    /// last key F.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().map(|c| c.to_ascii_lowercase()).collect();
+        let keys: [char; 16] = chars
+            .try_into()
+            .map_err(|_| format!("keymap '{}' needs exactly 16 characters", s))?;
+        for (i, c) in keys.iter().enumerate() {
+            if keys[..i].contains(c) {
+                return Err(format!("keymap '{}' uses '{}' twice", s, c));
+            }
+        }
+        Ok(Keymap(keys))
+    }
+}
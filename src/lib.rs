@@ -0,0 +1,1014 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP interpreter. `Chip8` is the machine,
+//! `step` runs one instruction and `program` runs frames against a
+//! frontend; the `chip8` binary is the terminal frontend.
+
+#![allow(non_snake_case, clippy::upper_case_acronyms)]
+
+pub mod asm;
+pub mod audio;
+pub mod blocks;
+pub mod bus;
+pub mod cartridge;
+pub mod database;
+pub mod frontend;
+pub mod hotkeys;
+pub mod keymap;
+pub mod quirks;
+pub mod random;
+pub mod record;
+pub mod render;
+pub mod rom;
+pub mod scheduler;
+pub mod stack;
+pub mod state;
+pub mod sys;
+#[cfg(test)]
+mod tests;
+pub mod timing;
+
+use audio::Pattern;
+use blocks::{Blocks, Engine};
+use bus::{Bus, Ram};
+use frontend::{Audio, Display, Input, InputEvent};
+use hotkeys::Action;
+use quirks::Quirks;
+use random::{Random, Xorshift};
+use record::Recorder;
+use rom::{Platform, PROGRAM_START};
+use scheduler::{Scheduler, Speed, FRAME_RATE};
+use stack::{Stack, StackError};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sys::{Handler, Sys};
+use timing::Timing;
+
+struct Timers {
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+}
+
+impl Timers {
+    fn new() -> Self {
+        Self {
+            delay_timer: 0,
+            sound_timer: 0,
+            audio_pattern: None,
+            pitch: 64,
+        }
+    }
+}
+
+/// Why a program stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Fault {
+    Stack(StackError),
+    /// 0NNN with nothing handling NNN, when that's an error.
+    Sys(u16),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fault::Stack(error) => write!(f, "{}", error),
+            Fault::Sys(address) => write!(f, "machine code call to {:03X}", address),
+        }
+    }
+}
+
+impl From<StackError> for Fault {
+    fn from(error: StackError) -> Fault {
+        Fault::Stack(error)
+    }
+}
+
+pub struct Chip8 {
+    pub program_counter: u16,
+    pub registers: [u8; 16],
+    stack: Stack,
+    pub i_register: u16,
+    timers: Arc<Mutex<Timers>>,
+    bus: Box<dyn Bus>,
+    /// Instructions already decoded, by address. Writes clear the entries
+    /// they touch, so programs that change their own code still work.
+    /// Fetching a cached instruction doesn't read the bus again.
+    decoded: Vec<Option<Instruction>>,
+    /// Only there with the block engine.
+    blocks: Option<Blocks>,
+    pub screen: [[u8; 64]; 32],
+    /// The last key pressed, until an instruction looks at it.
+    key: Option<u8>,
+    /// Set while Fx0A waits, so only a key pressed after it started counts.
+    waiting_for_key: bool,
+    /// Set by Dxyn with the display wait quirk, until the frame ends.
+    waiting_for_display: bool,
+    /// Why the program stopped, with the program counter still on the
+    /// instruction that failed. Nothing runs until a state is loaded.
+    fault: Option<Fault>,
+    sys: Sys,
+    /// Native stand-ins for machine code routines, by address.
+    handlers: HashMap<u16, Handler>,
+    /// Machine cycles left in the frame with VIP timing. Goes below zero
+    /// when an instruction runs past the end, and the next frame pays it back.
+    cycles: i64,
+    quirks: Quirks,
+    rng: Box<dyn Random>,
+}
+
+impl Chip8 {
+    pub fn new(platform: Platform, quirks: Quirks) -> Chip8 {
+        Self {
+            key: None,
+            waiting_for_key: false,
+            waiting_for_display: false,
+            fault: None,
+            sys: Sys::Ignore,
+            handlers: HashMap::new(),
+            cycles: 0,
+            quirks,
+            rng: Box::new(Xorshift::from_entropy()),
+            registers: [0; 16],
+            program_counter: 0x200,
+            i_register: 0,
+            stack: Stack::new(quirks.stack_depth),
+            screen: [[0; 64]; 32],
+            timers: Arc::new(Mutex::new(Timers::new())),
+            bus: Box::new(Ram::new(platform.memory_size())),
+            decoded: vec![None; platform.memory_size()],
+            blocks: None,
+        }
+    }
+    pub fn quirk_profile(&self) -> &'static str {
+        self.quirks.name()
+    }
+    /// The return addresses of the calls the program is in.
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+    /// What stopped the program, if something did. Loading a state starts
+    /// it again.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+    /// Whether a sprite was drawn with the display wait quirk on, so no more
+    /// instructions run until `tick_timers` starts the next frame.
+    pub fn waiting_for_display(&self) -> bool {
+        self.waiting_for_display
+    }
+    /// Whether running more instructions this frame would do nothing.
+    fn stopped(&self) -> bool {
+        self.waiting_for_display || self.fault.is_some()
+    }
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.blocks = match engine {
+            Engine::Interpreter => None,
+            Engine::Blocks => Some(Blocks::new(self.decoded.len())),
+        };
+    }
+    /// What 0NNN does for addresses without a handler.
+    pub fn set_sys(&mut self, sys: Sys) {
+        self.sys = sys;
+    }
+    /// Runs `handler` whenever the program calls the machine code at
+    /// `address` with 0NNN, replacing any handler it had.
+    pub fn on_sys(&mut self, address: u16, handler: impl FnMut(&mut Chip8) + 'static) {
+        self.handlers.insert(address & 0xFFF, Box::new(handler));
+    }
+    /// Makes CXNN produce the same numbers on every run.
+    pub fn seed(&mut self, seed: u64) {
+        self.set_rng(Box::new(Xorshift::seeded(seed)));
+    }
+    /// Where CXNN gets its numbers from, instead of the built in generator.
+    pub fn set_rng(&mut self, rng: Box<dyn Random>) {
+        self.rng = rng;
+    }
+    /// Puts `bus` between the interpreter and memory, e.g. to protect a
+    /// region or count accesses. Memory is whatever `bus` holds, so load the
+    /// program after this.
+    ///
+    /// An instruction is read through the bus the first time it's fetched
+    /// and comes from the decoded cache after that until something writes to
+    /// it, so the bus doesn't see fetches of code that already ran.
+    pub fn set_bus(&mut self, bus: Box<dyn Bus>) {
+        let memory_size = bus.memory().len();
+        self.bus = bus;
+        self.decoded = vec![None; memory_size];
+        if self.blocks.is_some() {
+            self.blocks = Some(Blocks::new(memory_size));
+        }
+    }
+    pub fn bus(&self) -> &dyn Bus {
+        self.bus.as_ref()
+    }
+    /// Writes through this can't be tracked, so it forgets every decoded
+    /// instruction.
+    pub fn bus_mut(&mut self) -> &mut dyn Bus {
+        self.decoded.fill(None);
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.clear();
+        }
+        self.bus.as_mut()
+    }
+    /// Puts the font at 0 and `program` at `PROGRAM_START`.
+    pub fn load(&mut self, program: &[u8]) {
+        let memory = self.bus_mut().memory_mut();
+        memory[..FONT.len()].copy_from_slice(&FONT);
+        memory[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+        // the instruction starting on the byte before covers this one too
+        let size = self.decoded.len();
+        let address = address as usize % size;
+        self.decoded[address] = None;
+        self.decoded[(address + size - 1) % size] = None;
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.invalidate(address as u16);
+        }
+    }
+    /// The two bytes of the instruction at the program counter, read through
+    /// the bus the way a fetch reads them.
+    pub fn opcode(&mut self) -> [u8; 2] {
+        self.bytes_at(self.program_counter)
+    }
+    fn bytes_at(&mut self, address: u16) -> [u8; 2] {
+        [
+            self.bus.read(address),
+            self.bus.read(address.wrapping_add(1)),
+        ]
+    }
+    fn decode_at(&mut self, address: u16) -> Option<Instruction> {
+        let [high, low] = self.bytes_at(address);
+        decode(high, low)
+    }
+    fn fetch(&mut self) -> Option<Instruction> {
+        let address = wrap(self.program_counter as usize, self.decoded.len());
+        if self.decoded[address].is_none() {
+            self.decoded[address] = self.decode_at(self.program_counter);
+        }
+        self.decoded[address]
+    }
+    fn SYS(&mut self, address: u16) -> Result<(), Fault> {
+        // taken out while it runs, since it needs the whole machine
+        if let Some(mut handler) = self.handlers.remove(&address) {
+            handler(self);
+            self.handlers.entry(address).or_insert(handler);
+            return Ok(());
+        }
+        match self.sys {
+            Sys::Ignore => Ok(()),
+            Sys::Error => Err(Fault::Sys(address)),
+        }
+    }
+    fn CLS(&mut self) {
+        self.screen = [[0; 64]; 32];
+    }
+    fn RET(&mut self) -> Result<(), StackError> {
+        self.program_counter = self.stack.pop()?;
+        Ok(())
+    }
+    fn JPaddr(&mut self, location: u16) {
+        self.program_counter = location;
+        self.program_counter = self.program_counter.overflowing_sub(2).0;
+    }
+    fn CallAddr(&mut self, location: u16) -> Result<(), StackError> {
+        self.stack.push(self.program_counter)?;
+        self.program_counter = location;
+        self.program_counter = self.program_counter.overflowing_sub(2).0;
+        Ok(())
+    }
+    fn SEVx(&mut self, register: u8, kk: u8) {
+        if self.registers[register as usize] == kk {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+    fn SNEVx(&mut self, register: u8, kk: u8) {
+        if self.registers[register as usize] != kk {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+    fn SEVxVy(&mut self, register: u8, register2: u8) {
+        if self.registers[register as usize] == self.registers[register2 as usize] {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+    fn LDVx(&mut self, register: u8, kk: u8) {
+        self.registers[register as usize] = kk
+    }
+    fn ADDVx(&mut self, register: u8, kk: u8) {
+        self.registers[register as usize] = self.registers[register as usize].wrapping_add(kk);
+    }
+    fn LDVxVy(&mut self, register: u8, register2: u8) {
+        self.registers[register as usize] = self.registers[register2 as usize]
+    }
+    fn ORVxVy(&mut self, register: u8, register2: u8) {
+        self.registers[register as usize] |= self.registers[register2 as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+    fn ANDVxVy(&mut self, register: u8, register2: u8) {
+        self.registers[register as usize] &= self.registers[register2 as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+    fn XORVxVy(&mut self, register: u8, register2: u8) {
+        self.registers[register as usize] ^= self.registers[register2 as usize];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+    }
+    fn ADDVxVy(&mut self, register: u8, register2: u8) {
+        let sum =
+            self.registers[register as usize].overflowing_add(self.registers[register2 as usize]);
+        self.registers[register as usize] = sum.0;
+        self.registers[0xF] = if sum.1 { 1 } else { 0 };
+    }
+    fn SUBVxVy(&mut self, register: u8, register2: u8) {
+        let sub =
+            self.registers[register as usize].overflowing_sub(self.registers[register2 as usize]);
+        self.registers[register as usize] = sub.0;
+        self.registers[0xF] = if sub.1 { 0 } else { 1 };
+    }
+    fn SHRVx(&mut self, register: u8, register_2: u8) {
+        if !self.quirks.shift_vx {
+            self.registers[register as usize] = self.registers[register_2 as usize];
+        }
+        let least_significant_beat = self.registers[register as usize] & 1;
+        self.registers[register as usize] >>= 1;
+        self.registers[0xF] = least_significant_beat;
+    }
+    fn SUBN(&mut self, register: u8, register2: u8) {
+        let sub =
+            self.registers[register2 as usize].overflowing_sub(self.registers[register as usize]);
+        self.registers[register as usize] = sub.0;
+        self.registers[0xF] = if sub.1 { 0 } else { 1 };
+    }
+    fn SHL(&mut self, register: u8, register_2: u8) {
+        if !self.quirks.shift_vx {
+            self.registers[register as usize] = self.registers[register_2 as usize];
+        }
+        let most_significant_bit = self.registers[register as usize] >> 7;
+        self.registers[register as usize] <<= 1;
+        self.registers[0xF] = most_significant_bit;
+    }
+    fn SNE(&mut self, register: u8, register2: u8) {
+        if self.registers[register as usize] != self.registers[register2 as usize] {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+    fn LDI(&mut self, nnn: u16) {
+        self.i_register = nnn;
+    }
+    fn JPV0ADDR(&mut self, nnn: u16) {
+        let register = if self.quirks.jump_vx { nnn >> 8 } else { 0 };
+        self.program_counter = nnn + self.registers[register as usize] as u16;
+        self.program_counter = self.program_counter.overflowing_sub(2).0;
+    }
+    fn RNDVx(&mut self, x: u8, kk: u8) {
+        let random_number = self.rng.byte();
+        self.registers[x as usize] = random_number & kk;
+    }
+    fn DRW(&mut self, x: u8, y: u8, n: u8) {
+        let height = self.screen.len();
+        let width = self.screen[0].len();
+        // the starting position always wraps, only the sprite itself is clipped
+        let y = self.registers[y as usize] as usize % height;
+        let x = self.registers[x as usize] as usize % width;
+        // after reading the position, which can be in VF
+        self.registers[0xF] = 0;
+        for i in 0..n as usize {
+            let byte = self.bus.read(self.i_register.wrapping_add(i as u16));
+            if self.quirks.clipping && y + i >= height {
+                break;
+            }
+            for z in 0..8 {
+                if self.quirks.clipping && x + z >= width {
+                    break;
+                }
+                let bit = (byte >> (7 - z)) & 1;
+                let new_y = (y + i) % height;
+                let new_x = (x + z) % width;
+                let was_on = self.screen[new_y][new_x] == 1;
+                self.screen[new_y][new_x] ^= bit;
+                let is_off = self.screen[new_y][new_x] == 0;
+                if was_on && is_off {
+                    self.registers[0xF] = 1;
+                }
+            }
+        }
+        self.waiting_for_display = self.quirks.display_wait;
+    }
+    fn SKP(&mut self, x: u8) {
+        if self.key == Some(self.registers[x as usize]) {
+            self.key = None;
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
+    fn SKNP(&mut self, x: u8) {
+        if self.key != Some(self.registers[x as usize]) {
+            self.program_counter = self.program_counter.wrapping_add(2);
+        } else {
+            self.key = None;
+        }
+    }
+    fn LDVxDT(&mut self, x: u8) {
+        let timers = self.timers.lock().unwrap();
+        self.registers[x as usize] = timers.delay_timer;
+    }
+    fn LDDTVx(&mut self, x: u8) {
+        let mut timers = self.timers.lock().unwrap();
+        timers.delay_timer = self.registers[x as usize];
+    }
+    fn LDSTVx(&mut self, x: u8) {
+        let mut timers = self.timers.lock().unwrap();
+        timers.sound_timer = self.registers[x as usize];
+    }
+    fn AUDIO(&mut self) {
+        let mut pattern = [0; 16];
+        for (i, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.bus.read(self.i_register.wrapping_add(i as u16));
+        }
+        let mut timers = self.timers.lock().unwrap();
+        timers.audio_pattern = Some(pattern);
+    }
+    fn PITCHVx(&mut self, x: u8) {
+        let mut timers = self.timers.lock().unwrap();
+        timers.pitch = self.registers[x as usize];
+    }
+    fn ADDIVx(&mut self, x: u8) {
+        self.i_register = self
+            .i_register
+            .wrapping_add(self.registers[x as usize] as u16);
+    }
+    fn LDFVx(&mut self, x: u8) {
+        let value = self.registers[x as usize];
+        self.i_register = value as u16 * 5;
+    }
+    fn LDBVx(&mut self, x: u8) {
+        let mut x = self.registers[x as usize];
+        let first = x % 10;
+        x /= 10;
+        let second = x % 10;
+        x /= 10;
+        let third = x % 10;
+        self.write(self.i_register, third);
+        self.write(self.i_register.wrapping_add(1), second);
+        self.write(self.i_register.wrapping_add(2), first);
+    }
+    fn LDIVx(&mut self, x: u8) {
+        let start = self.i_register;
+        for register in 0..=x as usize {
+            self.write(self.i_register, self.registers[register]);
+            self.i_register = self.i_register.wrapping_add(1);
+        }
+        if !self.quirks.memory_increment {
+            self.i_register = start;
+        }
+    }
+    fn LDVxI(&mut self, x: u8) {
+        let start = self.i_register;
+        for register in self.registers.iter_mut().take(x as usize + 1) {
+            *register = self.bus.read(self.i_register);
+            self.i_register = self.i_register.wrapping_add(1);
+        }
+        if !self.quirks.memory_increment {
+            self.i_register = start;
+        }
+    }
+    fn LDVxK(&mut self, x: u8) {
+        if !self.waiting_for_key {
+            self.waiting_for_key = true;
+            self.key = None;
+        }
+        match self.key.take() {
+            Some(key) => {
+                self.registers[x as usize] = key;
+                self.waiting_for_key = false;
+            }
+            // run Fx0A again until a key comes in, timers keep counting meanwhile
+            None => self.program_counter = self.program_counter.overflowing_sub(2).0,
+        }
+    }
+}
+
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    SysAddr(u16),
+    RET,
+    CLS,
+    JPaddr(u16),
+    CallAddr(u16),
+    SEVx(u8, u8),
+    SNEVx(u8, u8),
+    SEVxVy(u8, u8),
+    LDVx(u8, u8),
+    ADDVx(u8, u8),
+    LDVxVy(u8, u8),
+    ORVxVy(u8, u8),
+    ANDVxVy(u8, u8),
+    XORVxVy(u8, u8),
+    ADDVxVy(u8, u8),
+    SUBVxVy(u8, u8),
+    SHRVx(u8, u8),
+    SUBN(u8, u8),
+    SHL(u8, u8),
+    SNE(u8, u8),
+    LDI(u16),
+    JPV0ADDR(u16),
+    RNDVx(u8, u8),
+    DRW(u8, u8, u8),
+    SKP(u8),
+    SKNP(u8),
+    LDVxDT(u8),
+    LDVxK(u8),
+    LDDTVx(u8),
+    LDSTVx(u8),
+    AUDIO,
+    PITCHVx(u8),
+    ADDIVx(u8),
+    LDFVx(u8),
+    LDBVx(u8),
+    LDIVx(u8),
+    LDVxI(u8),
+}
+
+pub struct ParseInstructionError;
+
+impl Instruction {
+    /// Decodes the big endian opcode, the same way `from_str` decodes its hex.
+    pub fn from_opcode(opcode: u16) -> Option<Instruction> {
+        let x = (opcode >> 8 & 0xF) as u8;
+        let y = (opcode >> 4 & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let kk = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let instruction = match (opcode >> 12, n) {
+            _ if opcode == 0x00E0 => Instruction::CLS,
+            _ if opcode == 0x00EE => Instruction::RET,
+            _ if opcode == 0xF002 => Instruction::AUDIO,
+            (0x0, _) => Instruction::SysAddr(nnn),
+            (0x1, _) => Instruction::JPaddr(nnn),
+            (0x2, _) => Instruction::CallAddr(nnn),
+            (0x3, _) => Instruction::SEVx(x, kk),
+            (0x4, _) => Instruction::SNEVx(x, kk),
+            (0x5, 0x0) => Instruction::SEVxVy(x, y),
+            (0x6, _) => Instruction::LDVx(x, kk),
+            (0x7, _) => Instruction::ADDVx(x, kk),
+            (0x8, 0x0) => Instruction::LDVxVy(x, y),
+            (0x8, 0x1) => Instruction::ORVxVy(x, y),
+            (0x8, 0x2) => Instruction::ANDVxVy(x, y),
+            (0x8, 0x3) => Instruction::XORVxVy(x, y),
+            (0x8, 0x4) => Instruction::ADDVxVy(x, y),
+            (0x8, 0x5) => Instruction::SUBVxVy(x, y),
+            (0x8, 0x6) => Instruction::SHRVx(x, y),
+            (0x8, 0x7) => Instruction::SUBN(x, y),
+            (0x8, 0xE) => Instruction::SHL(x, y),
+            (0x9, 0x0) => Instruction::SNE(x, y),
+            (0xA, _) => Instruction::LDI(nnn),
+            (0xB, _) => Instruction::JPV0ADDR(nnn),
+            (0xC, _) => Instruction::RNDVx(x, kk),
+            (0xD, _) => Instruction::DRW(x, y, n),
+            (0xE, _) if kk == 0x9E => Instruction::SKP(x),
+            (0xE, _) if kk == 0xA1 => Instruction::SKNP(x),
+            (0xF, _) => match kk {
+                0x07 => Instruction::LDVxDT(x),
+                0x0A => Instruction::LDVxK(x),
+                0x15 => Instruction::LDDTVx(x),
+                0x18 => Instruction::LDSTVx(x),
+                0x3A => Instruction::PITCHVx(x),
+                0x1E => Instruction::ADDIVx(x),
+                0x29 => Instruction::LDFVx(x),
+                0x33 => Instruction::LDBVx(x),
+                0x55 => Instruction::LDIVx(x),
+                0x65 => Instruction::LDVxI(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+}
+
+impl FromStr for Instruction {
+    type Err = ParseInstructionError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: [char; 4] = {
+            let mut chars = s.chars();
+            [
+                chars.next().ok_or(ParseInstructionError)?,
+                chars.next().ok_or(ParseInstructionError)?,
+                chars.next().ok_or(ParseInstructionError)?,
+                chars.next().ok_or(ParseInstructionError)?,
+            ]
+        };
+        if chars == ['0', '0', 'E', '0'] {
+            return Ok(Instruction::CLS);
+        };
+        if chars == ['0', '0', 'E', 'E'] {
+            return Ok(Instruction::RET);
+        };
+        if chars == ['F', '0', '0', '2'] {
+            return Ok(Instruction::AUDIO);
+        };
+        if chars[0] == '0' {
+            return Ok(Instruction::SysAddr(chars_to_hex(&chars[1..])?));
+        }
+        if chars[0] == '1' {
+            return Ok(Instruction::JPaddr(chars_to_hex(&chars[1..])?));
+        }
+        if chars[0] == '2' {
+            return Ok(Instruction::CallAddr(chars_to_hex(&chars[1..])?));
+        }
+        if chars[0] == '3' {
+            return Ok(Instruction::SEVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..])? as u8,
+            ));
+        }
+        if chars[0] == '4' {
+            return Ok(Instruction::SNEVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..])? as u8,
+            ));
+        }
+        if chars[0] == '5' && chars[3] == '0' {
+            return Ok(Instruction::SEVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '6' {
+            return Ok(Instruction::LDVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..])? as u8,
+            ));
+        }
+        if chars[0] == '7' {
+            return Ok(Instruction::ADDVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '0' {
+            return Ok(Instruction::LDVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '1' {
+            return Ok(Instruction::ORVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '2' {
+            return Ok(Instruction::ANDVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '3' {
+            return Ok(Instruction::XORVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '4' {
+            return Ok(Instruction::ADDVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '5' {
+            return Ok(Instruction::SUBVxVy(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '6' {
+            return Ok(Instruction::SHRVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == '7' {
+            return Ok(Instruction::SUBN(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '8' && chars[3] == 'E' {
+            return Ok(Instruction::SHL(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == '9' && chars[3] == '0' {
+            return Ok(Instruction::SNE(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+            ));
+        }
+        if chars[0] == 'A' {
+            return Ok(Instruction::LDI(chars_to_hex(&chars[1..])?));
+        }
+        if chars[0] == 'B' {
+            return Ok(Instruction::JPV0ADDR(chars_to_hex(&chars[1..])?));
+        }
+        if chars[0] == 'C' {
+            return Ok(Instruction::RNDVx(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..])? as u8,
+            ));
+        }
+        if chars[0] == 'D' {
+            return Ok(Instruction::DRW(
+                chars_to_hex(&chars[1..=1])? as u8,
+                chars_to_hex(&chars[2..=2])? as u8,
+                chars_to_hex(&chars[3..=3])? as u8,
+            ));
+        }
+        if chars[0] == 'E' && chars[2] == '9' && chars[3] == 'E' {
+            return Ok(Instruction::SKP(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'E' && chars[2] == 'A' && chars[3] == '1' {
+            return Ok(Instruction::SKNP(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '0' && chars[3] == '7' {
+            return Ok(Instruction::LDVxDT(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '0' && chars[3] == 'A' {
+            return Ok(Instruction::LDVxK(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '1' && chars[3] == '5' {
+            return Ok(Instruction::LDDTVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '1' && chars[3] == '8' {
+            return Ok(Instruction::LDSTVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '3' && chars[3] == 'A' {
+            return Ok(Instruction::PITCHVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '1' && chars[3] == 'E' {
+            return Ok(Instruction::ADDIVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '2' && chars[3] == '9' {
+            return Ok(Instruction::LDFVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '3' && chars[3] == '3' {
+            return Ok(Instruction::LDBVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '5' && chars[3] == '5' {
+            return Ok(Instruction::LDIVx(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        if chars[0] == 'F' && chars[2] == '6' && chars[3] == '5' {
+            return Ok(Instruction::LDVxI(chars_to_hex(&chars[1..=1])? as u8));
+        }
+        Err(ParseInstructionError)
+    }
+}
+
+fn chars_to_hex(chars: &[char]) -> Result<u16, ParseInstructionError> {
+    u16::from_str_radix(&chars.iter().collect::<String>(), 16).map_err(|_| ParseInstructionError)
+}
+
+pub fn decode(high: u8, low: u8) -> Option<Instruction> {
+    Instruction::from_opcode(u16::from_be_bytes([high, low]))
+}
+
+pub fn step(chip8: &mut Chip8) {
+    let instruction = chip8.fetch();
+    execute(chip8, instruction);
+}
+
+/// `address % size` without dividing when it's already in range, which is
+/// almost always.
+fn wrap(address: usize, size: usize) -> usize {
+    if address < size {
+        address
+    } else {
+        address % size
+    }
+}
+
+/// Runs up to `instructions` instructions with the engine the machine is
+/// set to, stopping early when a sprite waits for the display or the program
+/// fails. Returns how many ran.
+pub fn run_instructions(chip8: &mut Chip8, instructions: u32) -> u32 {
+    if chip8.blocks.is_some() {
+        return blocks::run(chip8, instructions);
+    }
+    for executed in 0..instructions {
+        if chip8.stopped() {
+            return executed;
+        }
+        step(chip8);
+    }
+    instructions
+}
+
+/// Runs a frame's worth of COSMAC VIP machine cycles, one instruction at a
+/// time whatever the engine. Returns how many instructions ran.
+pub fn run_cycles(chip8: &mut Chip8) -> u32 {
+    chip8.cycles += timing::FRAME_BUDGET as i64;
+    let mut executed = 0;
+    while chip8.cycles > 0 && !chip8.stopped() {
+        let instruction = chip8.fetch();
+        chip8.cycles -= timing::cycles(chip8, instruction) as i64;
+        execute(chip8, instruction);
+        executed += 1;
+    }
+    // the rest of a frame spent waiting for the display is gone
+    if chip8.waiting_for_display {
+        chip8.cycles = chip8.cycles.min(0);
+    }
+    executed
+}
+
+/// Runs `instruction` as the one at the program counter and moves on,
+/// unless it fails or the program already stopped.
+fn execute(chip8: &mut Chip8, instruction: Option<Instruction>) {
+    if chip8.fault.is_some() {
+        return;
+    }
+    if let Some(instruction) = instruction {
+        if let Err(error) = read_instruction(instruction, chip8) {
+            chip8.fault = Some(error);
+            return;
+        }
+    };
+    // past the end of memory is the start again, as it is on the bus
+    let memory_size = chip8.decoded.len();
+    chip8.program_counter = wrap(chip8.program_counter as usize + 2, memory_size) as u16;
+}
+
+pub fn tick_timers(chip8: &mut Chip8) -> (bool, Option<Pattern>) {
+    let mut timers = chip8.timers.lock().unwrap();
+    let sound_on = timers.sound_timer > 0;
+    let pattern = timers.audio_pattern.map(|bits| Pattern {
+        bits,
+        pitch: timers.pitch,
+    });
+    timers.delay_timer = timers.delay_timer.saturating_sub(1);
+    timers.sound_timer = timers.sound_timer.saturating_sub(1);
+    // the display refreshes along with the timers
+    chip8.waiting_for_display = false;
+    (sound_on, pattern)
+}
+
+/// Runs the chip8 until a quit action comes in, one frame at a time as the
+/// scheduler allows.
+pub fn program(
+    chip8: &mut Chip8,
+    display: &mut dyn Display,
+    input: &mut dyn Input,
+    audio: &mut dyn Audio,
+    mut scheduler: Scheduler,
+    mut recorder: Option<Recorder>,
+    state_path: &Path,
+) -> io::Result<()> {
+    let mut last_draw: Option<Instant> = None;
+    loop {
+        for event in input.poll()? {
+            match event {
+                InputEvent::Key(key) => chip8.key = Some(key),
+                InputEvent::Action(Action::Quit) => {
+                    if let Some(recorder) = recorder.take() {
+                        recorder.finish()?;
+                    }
+                    return Ok(());
+                }
+                InputEvent::Action(Action::SaveState) => {
+                    display.message(save_state(chip8, state_path))
+                }
+                InputEvent::Action(Action::LoadState) => {
+                    display.message(load_state(chip8, state_path))
+                }
+                InputEvent::Action(action) => {
+                    if scheduler.handle(action) {
+                        display.message(format!("speed {}", scheduler.speed));
+                    }
+                }
+            }
+        }
+        if scheduler.run_frame() {
+            let running = chip8.fault.is_none();
+            let executed = match scheduler.timing {
+                Timing::Ipf => run_instructions(chip8, scheduler.ipf),
+                Timing::Vip => run_cycles(chip8),
+            };
+            scheduler.record_frame(executed);
+            if let (true, Some(fault)) = (running, chip8.fault) {
+                display.message(format!("{} at {:03X}", fault, chip8.program_counter));
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(&chip8.screen)?;
+            }
+            let (sound_on, pattern) = tick_timers(chip8);
+            // audio only keeps up with real time at normal speed
+            if scheduler.speed == Speed::Normal && !scheduler.paused {
+                let _ = audio.tick(sound_on, pattern);
+            }
+        }
+        // fast-forward can run far more frames than the terminal can show
+        let now = scheduler.now();
+        if last_draw.is_none_or(|last| now - last >= Duration::from_secs(1) / FRAME_RATE) {
+            display.draw(&chip8.screen, &scheduler, chip8.quirk_profile())?;
+            last_draw = Some(now);
+        }
+        scheduler.wait();
+    }
+}
+
+/// Returns the message for the HUD.
+fn save_state(chip8: &Chip8, path: &Path) -> String {
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(path, state::save(chip8)));
+    match result {
+        Ok(()) => "state saved".to_string(),
+        Err(error) => format!("couldn't save state to {}: {}", path.display(), error),
+    }
+}
+
+fn load_state(chip8: &mut Chip8, path: &Path) -> String {
+    let result = fs::read(path)
+        .map_err(|error| error.to_string())
+        .and_then(|bytes| state::load(chip8, &bytes));
+    match result {
+        Ok(()) => "state loaded".to_string(),
+        Err(error) => format!("couldn't load state from {}: {}", path.display(), error),
+    }
+}
+
+fn read_instruction(instruction: Instruction, chip8: &mut Chip8) -> Result<(), Fault> {
+    match instruction {
+        Instruction::SysAddr(location) => chip8.SYS(location)?,
+        Instruction::CLS => chip8.CLS(),
+        Instruction::RET => chip8.RET()?,
+        Instruction::JPaddr(location) => chip8.JPaddr(location),
+        Instruction::CallAddr(location) => chip8.CallAddr(location)?,
+        Instruction::SEVx(register, kk) => chip8.SEVx(register, kk),
+        Instruction::SNEVx(register, kk) => chip8.SNEVx(register, kk),
+        Instruction::SEVxVy(register, register2) => chip8.SEVxVy(register, register2),
+        Instruction::LDVx(register, kk) => chip8.LDVx(register, kk),
+        Instruction::ADDVx(register, kk) => chip8.ADDVx(register, kk),
+        Instruction::LDVxVy(register, register2) => chip8.LDVxVy(register, register2),
+        Instruction::ORVxVy(register, register2) => chip8.ORVxVy(register, register2),
+        Instruction::ANDVxVy(register, register2) => chip8.ANDVxVy(register, register2),
+        Instruction::XORVxVy(register, register2) => chip8.XORVxVy(register, register2),
+        Instruction::ADDVxVy(register, register2) => chip8.ADDVxVy(register, register2),
+        Instruction::SUBVxVy(register, register2) => chip8.SUBVxVy(register, register2),
+        Instruction::SHRVx(register, register_2) => chip8.SHRVx(register, register_2),
+        Instruction::SUBN(register, register2) => chip8.SUBN(register, register2),
+        Instruction::SHL(register, register_2) => chip8.SHL(register, register_2),
+        Instruction::SNE(register, register2) => chip8.SNE(register, register2),
+        Instruction::LDI(nnn) => chip8.LDI(nnn),
+        Instruction::JPV0ADDR(nnn) => chip8.JPV0ADDR(nnn),
+        Instruction::RNDVx(x, kk) => chip8.RNDVx(x, kk),
+        Instruction::DRW(x, y, n) => chip8.DRW(x, y, n),
+        Instruction::SKP(x) => chip8.SKP(x),
+        Instruction::SKNP(x) => chip8.SKNP(x),
+        Instruction::LDVxDT(x) => chip8.LDVxDT(x),
+        Instruction::LDDTVx(x) => chip8.LDDTVx(x),
+        Instruction::LDSTVx(x) => chip8.LDSTVx(x),
+        Instruction::AUDIO => chip8.AUDIO(),
+        Instruction::PITCHVx(x) => chip8.PITCHVx(x),
+        Instruction::ADDIVx(x) => chip8.ADDIVx(x),
+        Instruction::LDFVx(x) => chip8.LDFVx(x),
+        Instruction::LDBVx(x) => chip8.LDBVx(x),
+        Instruction::LDIVx(x) => chip8.LDIVx(x),
+        Instruction::LDVxI(x) => chip8.LDVxI(x),
+        Instruction::LDVxK(x) => chip8.LDVxK(x),
+    };
+    Ok(())
+}
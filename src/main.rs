@@ -1,694 +1,431 @@
+mod browser;
+mod cli;
+mod config;
+mod hud;
+mod tty;
+
+use browser::Browser;
+use chip8::audio::{AplaySink, AudioSink, Beeper, BellSink, NullSink, WavSink};
+use chip8::frontend::{Clock, FakeClock, HeadlessDisplay, InputEvent, ScriptedInput, SystemClock};
+use chip8::hotkeys::Action;
+use chip8::keymap::Keymap;
+use chip8::quirks::{QuirkPreset, Quirks};
+use chip8::record::Recorder;
+use chip8::render::{
+    self, KittyRenderer, Palette, RenderMode, Renderer, SixelRenderer, TextRenderer,
+};
+use chip8::rom::{self, Rom};
+use chip8::scheduler::{Scheduler, FRAME_RATE};
+use chip8::timing::Timing;
+use chip8::{asm, decode, program, run_cycles, run_instructions, state, step, tick_timers, Chip8};
+use cli::{Command, Options};
+use config::Config;
 use crossterm::terminal::SetSize;
 use crossterm::{
-    cursor,
-    event::{poll, read, Event, KeyCode},
-    queue,
-    style::*,
-    terminal::{self, Clear, EnterAlternateScreen},
+    terminal::{self, Clear, ClearType},
     ExecutableCommand,
 };
-use rand::prelude::*;
+use hud::Hud;
 use std::env;
-use std::error;
 use std::fs;
-use std::io::Write;
-use std::io::{stdout, Stdout};
-use std::ops::Index;
-use std::str::FromStr;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use std::io::{self, stdout, Stdout, Write};
+use std::time::Instant;
+use tty::{TerminalDisplay, TerminalGuard, TerminalInput};
 
-struct Timers {
-    delay_timer: u8,
-    sound_timer: u8,
+fn exit_with(message: String) -> ! {
+    tty::restore();
+    eprintln!("{}", message);
+    std::process::exit(1);
 }
 
-impl Timers {
-    fn new() -> Self {
-        Self {
-            delay_timer: 0,
-            sound_timer: 0,
-        }
+/// How big GIF recordings are compared to the chip8 screen.
+const RECORD_SCALE: usize = 4;
+const DEFAULT_TRACE_INSTRUCTIONS: u64 = 1000;
+const DEFAULT_BENCH_INSTRUCTIONS: u64 = 100_000_000;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    tty::install_panic_hook();
+    match cli::parse(&args).unwrap_or_else(|error| exit_with(error)) {
+        Command::Help(text) => print!("{}", text),
+        Command::Run(options) => {
+            run(options).unwrap_or_else(|error| exit_with(format!("terminal error: {}", error)))
+        }
+        Command::Browse(options) => {
+            browse(options).unwrap_or_else(|error| exit_with(format!("terminal error: {}", error)))
+        }
+        Command::Disasm(options) => disasm(options),
+        Command::Asm(options) => assemble(options),
+        Command::Info(options) => info(options),
+        Command::Trace(options) => trace(options),
+        Command::Bench(options) => bench(options),
     }
 }
 
-struct Chip8 {
-    program_counter: u16,
-    stack_counter: u16,
-    registers: [u8; 16],
-    stack: [u16; 16],
-    i_register: u16,
-    timers: Arc<Mutex<Timers>>,
-    memory: [u8; 4096],
-    screen: [[u8; 64]; 32],
-    current: char,
-    stdout: Stdout,
+/// How a rom runs, after combining the command line, the config file, the
+/// cartridge and the database.
+struct Settings {
+    ipf: u32,
+    quirks: Quirks,
+    keymap: Keymap,
+    palette: Palette,
+    render: RenderMode,
 }
 
-impl Chip8 {
-    fn new() -> Chip8 {
-        Self {
-            current: ' ',
-            registers: [0; 16],
-            program_counter: 0x200,
-            stack_counter: 0,
-            i_register: 0,
-            stack: [0; 16],
-            screen: [[0; 64]; 32],
-            timers: Arc::new(Mutex::new(Timers::new())),
-            memory: [0; 4096],
-            stdout: stdout(),
-        }
-    }
-    fn CLS(&mut self) {
-        self.screen = [[0; 64]; 32];
-    }
-    fn RET(&mut self) {
-        if self.stack_counter as usize >= self.stack.len() {
-            return ();
-        }
-        self.program_counter = *self.stack.index(self.stack_counter as usize);
-        self.stack_counter = self.stack_counter.overflowing_sub(1).0;
-    }
-    fn JPaddr(&mut self, location: u16) {
-        self.program_counter = location;
-        self.program_counter = self.program_counter.overflowing_sub(2).0;
-    }
-    fn CallAddr(&mut self, location: u16) {
-        self.stack_counter = self.stack_counter.overflowing_add(1).0;
-        self.stack[self.stack_counter as usize] = self.program_counter;
-        self.program_counter = location;
-        self.program_counter = self.program_counter.overflowing_sub(2).0;
-    }
-    fn SEVx(&mut self, register: u8, kk: u8) {
-        if self.registers[register as usize] == kk {
-            self.program_counter += 2;
-        }
-    }
-    fn SNEVx(&mut self, register: u8, kk: u8) {
-        if self.registers[register as usize] != kk {
-            self.program_counter += 2;
-        }
-    }
-    fn SEVxVy(&mut self, register: u8, register2: u8) {
-        if self.registers[register as usize] == self.registers[register2 as usize] {
-            self.program_counter += 2;
-        }
-    }
-    fn LDVx(&mut self, register: u8, kk: u8) {
-        self.registers[register as usize] = kk
-    }
-    fn ADDVx(&mut self, register: u8, kk: u8) {
-        self.registers[register as usize] = self.registers[register as usize].wrapping_add(kk);
-    }
-    fn LDVxVy(&mut self, register: u8, register2: u8) {
-        self.registers[register as usize] = self.registers[register2 as usize]
-    }
-    fn ORVxVy(&mut self, register: u8, register2: u8) {
-        self.registers[register as usize] |= self.registers[register2 as usize];
-        self.registers[0xF] = 0;
-    }
-    fn ANDVxVy(&mut self, register: u8, register2: u8) {
-        self.registers[register as usize] &= self.registers[register2 as usize];
-        self.registers[0xF] = 0;
-    }
-    fn XORVxVy(&mut self, register: u8, register2: u8) {
-        self.registers[register as usize] ^= self.registers[register2 as usize];
-        self.registers[0xF] = 0;
-    }
-    fn ADDVxVy(&mut self, register: u8, register2: u8) {
-        let sum =
-            self.registers[register as usize].overflowing_add(self.registers[register2 as usize]);
-        self.registers[register as usize] = sum.0;
-        self.registers[0xF] = if sum.1 { 1 } else { 0 };
-    }
-    fn SUBVxVy(&mut self, register: u8, register2: u8) {
-        let sub =
-            self.registers[register as usize].overflowing_sub(self.registers[register2 as usize]);
-        self.registers[register as usize] = sub.0;
-        self.registers[0xF] = if sub.1 { 0 } else { 1 };
-    }
-    fn SHRVx(&mut self, register: u8, register_2: u8) {
-        // todo!() MAKE THIS CONFIGURABL FOR THE USER
-        self.registers[register as usize] = self.registers[register_2 as usize];
-        let least_significant_beat = self.registers[register as usize] & 1;
-        self.registers[register as usize] >>= 1;
-        self.registers[0xF] = least_significant_beat;
-    }
-    fn SUBN(&mut self, register: u8, register2: u8) {
-        let sub =
-            self.registers[register2 as usize].overflowing_sub(self.registers[register as usize]);
-        self.registers[register as usize] = sub.0;
-        self.registers[0xF] = if sub.1 { 0 } else { 1 };
-    }
-    fn SHL(&mut self, register: u8, register_2: u8) {
-        // todo!() MAKE THIS CONFIGURABL FOR THE USER
-        self.registers[register as usize] = self.registers[register_2 as usize];
-        let most_significant_bit = self.registers[register as usize] >> 7;
-        self.registers[register as usize] <<= 1;
-        self.registers[0xF] = most_significant_bit;
-    }
-    fn SNE(&mut self, register: u8, register2: u8) {
-        if self.registers[register as usize] != self.registers[register2 as usize] {
-            self.program_counter += 2;
-        }
-    }
-    fn LDI(&mut self, nnn: u16) {
-        self.i_register = nnn;
-    }
-    fn JPV0ADDR(&mut self, nnn: u16) {
-        self.program_counter = nnn + self.registers[0] as u16;
-    }
-    fn RNDVx(&mut self, x: u8, kk: u8) {
-        let mut rng = rand::thread_rng();
-        let random_number: u8 = rng.gen_range(0..=255);
-        self.registers[x as usize] = random_number & kk;
-    }
-    fn DRW(&mut self, x: u8, y: u8, n: u8) {
-        self.registers[0xF] = 0;
-        let y = self.registers[y as usize] as usize;
-        let x = self.registers[x as usize] as usize;
-        let bytes = &self.memory[self.i_register as usize..(self.i_register + n as u16) as usize];
-        let mut overflow = false;
-        for (i, byte) in bytes.iter().enumerate() {
-            if y >= self.screen.len() || x >= self.screen[0].len() {
-                overflow = true;
-            }
-            for z in 0..8 {
-                if !overflow && (y + i >= self.screen.len() || x + z >= self.screen[0].len()) {
-                    break;
-                }
-                let bit = (byte >> (7 - z)) & 1;
-                let new_y = (y + i) % self.screen.len();
-                let new_x = (x + z) % self.screen[0].len();
-                let was_on = self.screen[new_y][new_x] == 1;
-                self.screen[new_y][new_x] ^= bit;
-                let is_off = self.screen[new_y][new_x] == 0;
-                if was_on && is_off {
-                    self.registers[0xF] = 1;
-                }
-            }
-        }
-    }
-    fn SKP(&mut self, x: u8) {
-        if self.current
-            == format!("{:X}", self.registers[x as usize])
-                .chars()
-                .next()
-                .unwrap()
-        {
-            self.current = ' ';
-            self.program_counter += 2;
-        }
-    }
-    fn SKNP(&mut self, x: u8) {
-        if self.current
-            != format!("{:X}", self.registers[x as usize])
-                .chars()
-                .next()
-                .unwrap()
-        {
-            self.program_counter += 2;
-        } else {
-            self.current = ' ';
-        }
-    }
-    fn LDVxDT(&mut self, x: u8) {
-        let timers = self.timers.lock().unwrap();
-        self.registers[x as usize] = timers.delay_timer;
-    }
-    fn LDDTVx(&mut self, x: u8) {
-        let mut timers = self.timers.lock().unwrap();
-        timers.delay_timer = self.registers[x as usize];
-    }
-    fn LDSTVx(&mut self, x: u8) {
-        let mut timers = self.timers.lock().unwrap();
-        timers.sound_timer = self.registers[x as usize];
-    }
-    fn ADDIVx(&mut self, x: u8) {
-        self.i_register += self.registers[x as usize] as u16;
-    }
-    fn LDFVx(&mut self, x: u8) {
-        let value = self.registers[x as usize];
-        self.i_register = value as u16 * 5;
-    }
-    fn LDBVx(&mut self, x: u8) {
-        let mut x = self.registers[x as usize];
-        let first = x % 10;
-        x /= 10;
-        let second = x % 10;
-        x /= 10;
-        let third = x % 10;
-        self.memory[self.i_register as usize] = third;
-        self.memory[self.i_register as usize + 1] = second;
-        self.memory[self.i_register as usize + 2] = first;
-    }
-    fn LDIVx(&mut self, x: u8) {
-        // Make this configurable
-        for (i, register) in self.registers.iter().take(x as usize + 1).enumerate() {
-            self.memory[(self.i_register as usize)] = *register;
-            self.i_register += 1;
-        }
-    }
-    fn LDVxI(&mut self, x: u8) {
-        // Make this configurable
-        for (i, register) in self.registers.iter_mut().take(x as usize + 1).enumerate() {
-            *register = self.memory[(self.i_register as usize)];
-            self.i_register += 1;
-        }
-    }
-    fn LDVxK(&mut self, x: u8) {
-        loop {
-            if let Some(val) = handle_input() {
-                self.registers[x as usize] = val;
-                break;
-            }
-        }
+fn settings(rom: &Rom, options: &Options, config: &Config) -> Settings {
+    // the command line wins over the config's section for this rom, then
+    // what the rom asks for, and the config's defaults come last
+    let defaults = config.defaults;
+    let builtin = rom::Settings::default();
+    let asked = rom.settings(rom::Settings {
+        ipf: defaults.ipf.unwrap_or(builtin.ipf),
+        quirks: defaults.quirks.map_or(builtin.quirks, QuirkPreset::quirks),
+        keymap: defaults.keymap.unwrap_or(builtin.keymap),
+        palette: defaults.palette.unwrap_or(builtin.palette),
+    });
+    let user = config.rom(rom);
+    Settings {
+        ipf: options.ipf.or(user.ipf).unwrap_or(asked.ipf),
+        quirks: options
+            .quirks
+            .or(user.quirks)
+            .map_or(asked.quirks, QuirkPreset::quirks),
+        keymap: options.keymap.or(user.keymap).unwrap_or(asked.keymap),
+        palette: options.palette.or(user.palette).unwrap_or(asked.palette),
+        render: options
+            .render
+            .or(user.render)
+            .or(defaults.render)
+            .unwrap_or(RenderMode::Text),
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <file_path>", args[0]);
-        std::process::exit(1);
-    }
-
-    // Get the file path from the arguments
-    let file_path = &args[1];
-    let instructions = fs::read(file_path).unwrap_or_else(|_| {
-        eprintln!("couldn't open file");
-        std::process::exit(1);
-    });
-    let mut stdout: Stdout = stdout();
-    terminal::enable_raw_mode().unwrap();
-    stdout.execute(EnterAlternateScreen).unwrap();
-    stdout.execute(SetSize(32, 64)).unwrap();
-    program(instructions);
+fn load_config() -> Config {
+    Config::load().unwrap_or_else(|error| exit_with(error))
 }
 
-pub const FONT: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+fn load_rom(options: &Options) -> Rom {
+    Rom::load(&options.file, options.platform).unwrap_or_else(|error| exit_with(error.to_string()))
+}
 
-enum Instruction {
-    SysAddr(u16),
-    RET,
-    CLS,
-    JPaddr(u16),
-    CallAddr(u16),
-    SEVx(u8, u8),
-    SNEVx(u8, u8),
-    SEVxVy(u8, u8),
-    LDVx(u8, u8),
-    ADDVx(u8, u8),
-    LDVxVy(u8, u8),
-    ORVxVy(u8, u8),
-    ANDVxVy(u8, u8),
-    XORVxVy(u8, u8),
-    ADDVxVy(u8, u8),
-    SUBVxVy(u8, u8),
-    SHRVx(u8, u8),
-    SUBN(u8, u8),
-    SHL(u8, u8),
-    SNE(u8, u8),
-    LDI(u16),
-    JPV0ADDR(u16),
-    RNDVx(u8, u8),
-    DRW(u8, u8, u8),
-    SKP(u8),
-    SKNP(u8),
-    LDVxDT(u8),
-    LDVxK(u8),
-    LDDTVx(u8),
-    LDSTVx(u8),
-    ADDIVx(u8),
-    LDFVx(u8),
-    LDBVx(u8),
-    LDIVx(u8),
-    LDVxI(u8),
+/// A chip8 with the rom loaded, ready for its first instruction.
+fn machine(rom: &Rom, settings: &Settings, options: &Options) -> Chip8 {
+    let mut chip8 = Chip8::new(rom.platform, settings.quirks);
+    if let Some(seed) = options.seed {
+        chip8.seed(seed);
+    }
+    chip8.load(&rom.bytes);
+    chip8.set_engine(options.engine.unwrap_or_default());
+    chip8.set_sys(options.sys.unwrap_or_default());
+    chip8
 }
 
-struct ParseInstructionError;
+fn scheduler(settings: &Settings, options: &Options, clock: Box<dyn Clock>) -> Scheduler {
+    let mut scheduler = Scheduler::new(settings.ipf, clock);
+    scheduler.timing = options.timing.unwrap_or_default();
+    scheduler
+}
 
-impl FromStr for Instruction {
-    type Err = ParseInstructionError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let chars: [char; 4] = {
-            let mut chars = s.chars();
-            [
-                chars.next().ok_or(ParseInstructionError)?,
-                chars.next().ok_or(ParseInstructionError)?,
-                chars.next().ok_or(ParseInstructionError)?,
-                chars.next().ok_or(ParseInstructionError)?,
-            ]
-        };
-        if chars == ['0', '0', 'E', '0'] {
-            return Ok(Instruction::CLS);
-        };
-        if chars == ['0', '0', 'E', 'E'] {
-            return Ok(Instruction::RET);
+fn run(options: Options) -> io::Result<()> {
+    let config = load_config();
+    let rom = load_rom(&options);
+    if let Some(frames) = options.frames {
+        let settings = settings(&rom, &options, &config);
+        let mut chip8 = machine(&rom, &settings, &options);
+        let sink: Box<dyn AudioSink + Send> = match wav(&options) {
+            Some(sink) => Box::new(sink),
+            None => Box::new(NullSink),
         };
-        if chars[0] == '0' {
-            return Ok(Instruction::SysAddr(chars_to_hex(&chars[1..])?));
-        }
-        if chars[0] == '1' {
-            return Ok(Instruction::JPaddr(chars_to_hex(&chars[1..])?));
-        }
-        if chars[0] == '2' {
-            return Ok(Instruction::CallAddr(chars_to_hex(&chars[1..])?));
-        }
-        if chars[0] == '3' {
-            return Ok(Instruction::SEVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..])? as u8,
-            ));
-        }
-        if chars[0] == '4' {
-            return Ok(Instruction::SNEVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..])? as u8,
-            ));
-        }
-        if chars[0] == '5' && chars[3] == '5' {
-            return Ok(Instruction::SEVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '6' {
-            return Ok(Instruction::LDVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..])? as u8,
-            ));
-        }
-        if chars[0] == '7' {
-            return Ok(Instruction::ADDVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '0' {
-            return Ok(Instruction::LDVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '1' {
-            return Ok(Instruction::ORVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '2' {
-            return Ok(Instruction::ANDVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '3' {
-            return Ok(Instruction::XORVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '4' {
-            return Ok(Instruction::ADDVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '5' {
-            return Ok(Instruction::SUBVxVy(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '6' {
-            return Ok(Instruction::SHRVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == '7' {
-            return Ok(Instruction::SUBN(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '8' && chars[3] == 'E' {
-            return Ok(Instruction::SHL(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == '9' && chars[3] == '0' {
-            return Ok(Instruction::SNE(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-            ));
-        }
-        if chars[0] == 'A' {
-            return Ok(Instruction::LDI(chars_to_hex(&chars[1..])?));
-        }
-        if chars[0] == 'B' {
-            return Ok(Instruction::JPV0ADDR(chars_to_hex(&chars[1..])?));
-        }
-        if chars[0] == 'C' {
-            return Ok(Instruction::RNDVx(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..])? as u8,
-            ));
-        }
-        if chars[0] == 'D' {
-            return Ok(Instruction::DRW(
-                chars_to_hex(&chars[1..=1])? as u8,
-                chars_to_hex(&chars[2..=2])? as u8,
-                chars_to_hex(&chars[3..=3])? as u8,
-            ));
-        }
-        if chars[0] == 'E' && chars[2] == '9' && chars[3] == 'E' {
-            return Ok(Instruction::SKP(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'E' && chars[2] == 'A' && chars[3] == '1' {
-            return Ok(Instruction::SKNP(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '0' && chars[3] == '7' {
-            return Ok(Instruction::LDVxDT(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '0' && chars[3] == 'A' {
-            return Ok(Instruction::LDVxK(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '1' && chars[3] == '5' {
-            return Ok(Instruction::LDDTVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '1' && chars[3] == '8' {
-            return Ok(Instruction::LDSTVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '1' && chars[3] == 'E' {
-            return Ok(Instruction::ADDIVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '2' && chars[3] == '9' {
-            return Ok(Instruction::LDFVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '3' && chars[3] == '3' {
-            return Ok(Instruction::LDBVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '5' && chars[3] == '5' {
-            return Ok(Instruction::LDIVx(chars_to_hex(&chars[1..=1])? as u8));
-        }
-        if chars[0] == 'F' && chars[2] == '6' && chars[3] == '5' {
-            return Ok(Instruction::LDVxI(chars_to_hex(&chars[1..=1])? as u8));
+        program(
+            &mut chip8,
+            &mut HeadlessDisplay,
+            &mut ScriptedInput::new(vec![(frames, InputEvent::Action(Action::Quit))]),
+            &mut Beeper::new(options.tone, sink),
+            scheduler(&settings, &options, Box::new(FakeClock::default())),
+            recorder(&options, &settings.palette),
+            &state::path(&config.save_dir(), &rom.sha1),
+        )?;
+        print!("{}", render::text(&chip8.screen));
+        if let Some(fault) = chip8.fault() {
+            eprintln!("stopped: {} at {:03X}", fault, chip8.program_counter);
+        }
+        return Ok(());
+    }
+    let _guard = TerminalGuard::enter()?;
+    play(&rom, &options, &config)
+}
+
+/// Lists the roms in a directory and plays the one picked until the user
+/// leaves the list.
+fn browse(options: Options) -> io::Result<()> {
+    let config = load_config();
+    let mut browser = Browser::scan(&options.file).unwrap_or_else(|error| {
+        exit_with(format!(
+            "couldn't read {}: {}",
+            options.file.display(),
+            error
+        ))
+    });
+    let _guard = TerminalGuard::enter()?;
+    while let Some(path) = browser.choose(&mut stdout())? {
+        match Rom::load(&path, options.platform) {
+            Ok(rom) => play(&rom, &options, &config)?,
+            Err(error) => browser.message(format!("{}: {}", path.display(), error)),
         }
-        Err(ParseInstructionError)
     }
+    Ok(())
 }
 
-fn chars_to_hex(chars: &[char]) -> Result<u16, ParseInstructionError> {
-    u16::from_str_radix(&chars.iter().collect::<String>(), 16).map_err(|_| ParseInstructionError)
+fn recorder(options: &Options, palette: &Palette) -> Option<Recorder> {
+    options.record.as_ref().map(|path| {
+        Recorder::create(path, palette, RECORD_SCALE).unwrap_or_else(|error| {
+            exit_with(format!("couldn't create {}: {}", path.display(), error))
+        })
+    })
 }
 
-fn numbers_to_hex(num_1: u8, num_2: u8) -> String {
-    let num_1 = format!("{:X}", num_1);
-    let num_1 = if num_1.len() == 1 {
-        format!("0{}", num_1)
-    } else {
-        num_1
+fn wav(options: &Options) -> Option<WavSink<fs::File>> {
+    options.wav.as_ref().map(|path| {
+        let file = fs::File::create(path).unwrap_or_else(|error| {
+            exit_with(format!("couldn't create {}: {}", path.display(), error))
+        });
+        WavSink::new(file).unwrap()
+    })
+}
+
+/// Plays `rom` in the terminal, which must already be in raw mode on the
+/// alternate screen, until the user quits.
+fn play(rom: &Rom, options: &Options, config: &Config) -> io::Result<()> {
+    let settings = settings(rom, options, config);
+    let mut chip8 = machine(rom, &settings, options);
+    let recorder = recorder(options, &settings.palette);
+    let wav = wav(options);
+    let mut hud = Hud::new(rom.name(), !options.no_hud);
+    match rom.entry {
+        Some(entry) => hud.message(format!("{} by {}", entry.title, entry.author)),
+        None => hud.message("unknown rom, using default settings"),
+    }
+    hud.message(format!("crc32 {:08x} sha1 {}", rom.crc32, rom.sha1));
+    for warning in &rom.warnings {
+        hud.message(format!("warning: {}", warning));
+    }
+    let palette = settings.palette;
+    let renderer: Box<dyn Renderer> = match settings.render {
+        RenderMode::Text => Box::new(TextRenderer::new(stdout(), palette)),
+        RenderMode::Sixel => Box::new(SixelRenderer::new(stdout(), 8, palette)),
+        RenderMode::Kitty => Box::new(KittyRenderer::new(stdout(), 8, palette)),
     };
-    let num_2 = format!("{:X}", num_2);
-    let num_2 = if num_2.len() == 1 {
-        format!("0{}", num_2)
+    let sink: Box<dyn AudioSink + Send> = if let Some(sink) = wav {
+        Box::new(sink)
+    } else if options.mute {
+        Box::new(NullSink)
     } else {
-        num_2
+        match AplaySink::new() {
+            Ok(sink) => Box::new(sink),
+            Err(_) => {
+                hud.message("aplay not found, using the terminal bell");
+                Box::new(BellSink::new(stdout()))
+            }
+        }
     };
-    format!("{}{}", num_1, num_2)
+    let mut stdout: Stdout = stdout();
+    // grow the terminal to fit the screen and the status line, never shrink it
+    let (columns, rows) = terminal::size().unwrap_or((0, 0));
+    let needed_rows = renderer.rows() + if hud.visible { 1 } else { 0 };
+    stdout.execute(Clear(ClearType::All))?.execute(SetSize(
+        columns.max(renderer.columns()),
+        rows.max(needed_rows),
+    ))?;
+    program(
+        &mut chip8,
+        &mut TerminalDisplay::new(renderer, hud),
+        &mut TerminalInput {
+            keymap: settings.keymap,
+            hotkeys: &config.hotkeys,
+        },
+        &mut Beeper::new(options.tone, sink),
+        scheduler(&settings, options, Box::new(SystemClock)),
+        recorder,
+        &state::path(&config.save_dir(), &rom.sha1),
+    )
 }
 
-fn program(instructions: Vec<u8>) {
-    let mut chip8 = Chip8::new();
-    for (i, instruction) in instructions.iter().enumerate() {
-        chip8.memory[0x200 + i] = *instruction;
-    }
-    for (i, font) in FONT.into_iter().enumerate() {
-        chip8.memory[i] = font;
-    }
-    {
-        let timers = Arc::clone(&chip8.timers);
-        let timers_thread = thread::spawn(move || loop {
-            let timers = Arc::clone(&timers);
-            let mut timers = timers.lock().unwrap();
-            timers.delay_timer = timers.delay_timer.saturating_sub(1);
-            timers.sound_timer = timers.sound_timer.saturating_sub(1);
-            std::mem::drop(timers);
-            thread::sleep(Duration::from_millis(16));
-        });
+fn disasm(options: Options) {
+    let rom = load_rom(&options);
+    // ignore errors from a closed pipe, e.g. when piped into head
+    let _ = stdout().write_all(asm::disassemble(&rom.bytes).as_bytes());
+}
+
+fn assemble(options: Options) {
+    let source = fs::read_to_string(&options.file).unwrap_or_else(|error| {
+        exit_with(format!(
+            "couldn't read {}: {}",
+            options.file.display(),
+            error
+        ))
+    });
+    let program = asm::assemble(&source)
+        .unwrap_or_else(|error| exit_with(format!("{}: {}", options.file.display(), error)));
+    let output = options
+        .output
+        .unwrap_or_else(|| options.file.with_extension("ch8"));
+    if output == options.file {
+        exit_with(format!(
+            "refusing to overwrite the source {}, pick another name with --output",
+            output.display()
+        ));
+    }
+    fs::write(&output, &program).unwrap_or_else(|error| {
+        exit_with(format!("couldn't write {}: {}", output.display(), error))
+    });
+    println!("wrote {} bytes to {}", program.len(), output.display());
+}
+
+fn info(options: Options) {
+    let rom = load_rom(&options);
+    let settings = settings(&rom, &options, &load_config());
+    println!("file      {}", rom.path.display());
+    if let Some(entry) = rom.entry {
+        println!("title     {}", entry.title);
+        println!("author    {}", entry.author);
+    }
+    if rom.cartridge.is_some() {
+        println!("format    Octo cartridge");
+    }
+    println!("platform  {}", rom.platform);
+    println!("size      {} bytes", rom.bytes.len());
+    println!("crc32     {:08x}", rom.crc32);
+    println!("sha1      {}", rom.sha1);
+    println!("ipf       {}", settings.ipf);
+    println!("quirks    {}", settings.quirks.name());
+    println!("keymap    {}", settings.keymap);
+    println!("theme     {}", settings.palette);
+    for warning in &rom.warnings {
+        println!("warning   {}", warning);
     }
-    let mut old_screen = chip8.screen.clone();
-    loop {
-        if (chip8.timers.clone()).lock().unwrap().sound_timer > 0 {
-            println!("\x07");
+}
+
+fn trace(options: Options) {
+    let rom = load_rom(&options);
+    let settings = settings(&rom, &options, &load_config());
+    let mut chip8 = machine(&rom, &settings, &options);
+    let mut out = io::BufWriter::new(stdout().lock());
+    let instructions = options.instructions.unwrap_or(DEFAULT_TRACE_INSTRUCTIONS);
+    write_trace(&mut chip8, instructions, settings.ipf, &mut out);
+}
+
+/// Runs `instructions` instructions and writes a line for each, stopping
+/// early when the program fails or `out` goes away.
+fn write_trace(chip8: &mut Chip8, instructions: u64, ipf: u32, out: &mut impl Write) {
+    let mut in_frame = 0;
+    for _ in 0..instructions {
+        let pc = chip8.program_counter;
+        let [high, low] = chip8.opcode();
+        let code =
+            decode(high, low).map_or("???".to_string(), |instruction| instruction.to_string());
+        step(chip8);
+        let registers: Vec<String> = chip8
+            .registers
+            .iter()
+            .map(|register| format!("{:02X}", register))
+            .collect();
+        // the call stack, outermost call first
+        let stack = if chip8.stack().is_empty() {
+            String::new()
+        } else {
+            format!("  S {}", chip8.stack())
+        };
+        let line = writeln!(
+            out,
+            "{:03X}  {:02X}{:02X}  {:<18} V {}  I {:03X}{}",
+            pc,
+            high,
+            low,
+            code,
+            registers.join(" "),
+            chip8.i_register,
+            stack
+        );
+        // stop quietly once whatever reads the trace goes away
+        if line.is_err() {
+            return;
         }
-        if poll(Duration::from_millis(0)).unwrap() {
-            if let Event::Key(event) = read().unwrap() {
-                if let KeyCode::Char(m) = event.code {
-                    // chip8
-                    //     .stdout
-                    //     .execute(Print(format!("p{}\n\r", chip8.current)))
-                    //     .unwrap();
-                    chip8.current = m;
-                }
-                if event.code == KeyCode::Char('q') {
-                    chip8
-                        .stdout
-                        .execute(Print("You pressed 'q'. Exiting...\n"))
-                        .unwrap();
-                    std::process::exit(1);
-                }
-            }
+        if let Some(fault) = chip8.fault() {
+            let _ = writeln!(out, "stopped: {}", fault);
+            return;
         }
-        let hex = numbers_to_hex(
-            chip8.memory[chip8.program_counter as usize],
-            chip8.memory[chip8.program_counter as usize + 1],
-        );
-        if let Ok(insruction) = Instruction::from_str(&hex) {
-            read_instruction(insruction, &mut chip8).unwrap();
-        };
-        chip8.program_counter = chip8.program_counter.overflowing_add(2).0;
-        chip8.stdout.flush().unwrap();
-        let mut screen = String::new();
-        for (y, row) in chip8.screen.iter().enumerate() {
-            for (x, value) in row.iter().enumerate() {
-                if old_screen[y][x] == *value {
-                    continue;
-                }
-                let pixel = if *value == 0 { ' ' } else { '#' };
-                chip8.stdout.execute(cursor::MoveTo(x as u16, y as u16));
-                chip8.stdout.execute(Print(pixel));
-            }
-            // let row = format!(
-            //     "{}\n\r",
-            //     line.iter()
-            //         .map(|l| {
-            //             if *l == 0 {
-            //                 ' '
-            //             } else {
-            //                 '#'
-            //             }
-            //         })
-            //         .collect::<String>()
-            // );
-            // screen = format!("{screen}{row}");
+        in_frame += 1;
+        if in_frame == ipf || chip8.waiting_for_display() {
+            tick_timers(chip8);
+            in_frame = 0;
         }
-        old_screen = chip8.screen.clone();
-        // queue!(chip8.stdout, Clear(terminal::ClearType::All), Print(screen)).unwrap();
-        thread::sleep(Duration::from_millis(1));
     }
 }
 
-fn read_instruction(
-    instruction: Instruction,
-    chip8: &mut Chip8,
-) -> Result<(), Box<dyn error::Error>> {
-    match instruction {
-        Instruction::SysAddr(_location) => {
-            //
+fn bench(options: Options) {
+    let rom = load_rom(&options);
+    let settings = settings(&rom, &options, &load_config());
+    let mut chip8 = machine(&rom, &settings, &options);
+    let instructions = options.instructions.unwrap_or(DEFAULT_BENCH_INSTRUCTIONS);
+    let timing = options.timing.unwrap_or_default();
+    let start = Instant::now();
+    let mut executed = 0;
+    let mut frames = 0;
+    while executed < instructions {
+        executed += match timing {
+            Timing::Ipf => {
+                let frame = (settings.ipf as u64).min(instructions - executed);
+                run_instructions(&mut chip8, frame as u32) as u64
+            }
+            Timing::Vip => run_cycles(&mut chip8) as u64,
+        };
+        tick_timers(&mut chip8);
+        frames += 1;
+        if chip8.fault().is_some() {
+            break;
         }
-        Instruction::CLS => chip8.CLS(),
-        Instruction::RET => chip8.RET(),
-        Instruction::JPaddr(location) => chip8.JPaddr(location),
-        Instruction::CallAddr(location) => chip8.CallAddr(location),
-        Instruction::SEVx(register, kk) => chip8.SEVx(register, kk),
-        Instruction::SNEVx(register, kk) => chip8.SNEVx(register, kk),
-        Instruction::SEVxVy(register, register2) => chip8.SEVxVy(register, register2),
-        Instruction::LDVx(register, kk) => chip8.registers[register as usize] = kk,
-        Instruction::ADDVx(register, kk) => chip8.ADDVx(register, kk),
-        Instruction::LDVxVy(register, register2) => chip8.LDVxVy(register, register2),
-        Instruction::ORVxVy(register, register2) => chip8.ORVxVy(register, register2),
-        Instruction::ANDVxVy(register, register2) => chip8.ANDVxVy(register, register2),
-        Instruction::XORVxVy(register, register2) => chip8.XORVxVy(register, register2),
-        Instruction::ADDVxVy(register, register2) => chip8.ADDVxVy(register, register2),
-        Instruction::SUBVxVy(register, register2) => chip8.SUBVxVy(register, register2),
-        Instruction::SHRVx(register, register_2) => chip8.SHRVx(register, register_2),
-        Instruction::SUBN(register, register2) => chip8.SUBN(register, register2),
-        Instruction::SHL(register, register_2) => chip8.SHL(register, register_2),
-        Instruction::SNE(register, register2) => chip8.SNE(register, register2),
-        Instruction::LDI(nnn) => chip8.LDI(nnn),
-        Instruction::JPV0ADDR(nnn) => chip8.JPV0ADDR(nnn),
-        Instruction::RNDVx(x, kk) => chip8.RNDVx(x, kk),
-        Instruction::DRW(x, y, n) => chip8.DRW(x, y, n),
-        Instruction::SKP(x) => chip8.SKP(x),
-        Instruction::SKNP(x) => chip8.SKNP(x),
-        Instruction::LDVxDT(x) => chip8.LDVxDT(x),
-        Instruction::LDDTVx(x) => chip8.LDDTVx(x),
-        Instruction::LDSTVx(x) => chip8.LDSTVx(x),
-        Instruction::ADDIVx(x) => chip8.ADDIVx(x),
-        Instruction::LDFVx(x) => chip8.LDFVx(x),
-        Instruction::LDBVx(x) => chip8.LDBVx(x),
-        Instruction::LDIVx(x) => chip8.LDIVx(x),
-        Instruction::LDVxI(x) => chip8.LDVxI(x),
-        Instruction::LDVxK(x) => chip8.LDVxK(x),
+    }
+    let elapsed = start.elapsed();
+    let per_second = executed as f64 / elapsed.as_secs_f64();
+    let timing = match timing {
+        Timing::Ipf => format!("{} ipf", settings.ipf),
+        Timing::Vip => "vip timing".to_string(),
     };
-    Ok(())
+    println!(
+        "{}: {} instructions in {:.2?}, {:.2}M instructions/s, {:.0}x real time at {}",
+        rom.name(),
+        executed,
+        elapsed,
+        per_second / 1e6,
+        frames as f64 / FRAME_RATE as f64 / elapsed.as_secs_f64(),
+        timing
+    );
+    if let Some(fault) = chip8.fault() {
+        eprintln!("stopped: {} at {:03X}", fault, chip8.program_counter);
+    }
 }
 
-fn handle_input() -> Option<u8> {
-    if let Event::Key(event) = read().unwrap() {
-        match event.code {
-            KeyCode::Char('1') => Some(0x1),
-            KeyCode::Char('2') => Some(0x2),
-            KeyCode::Char('3') => Some(0x3),
-            KeyCode::Char('4') => Some(0xC),
-            KeyCode::Char('Q') => Some(0x4),
-            KeyCode::Char('W') => Some(0x5),
-            KeyCode::Char('E') => Some(0x6),
-            KeyCode::Char('R') => Some(0xD),
-            KeyCode::Char('A') => Some(0x7),
-            KeyCode::Char('S') => Some(0x8),
-            KeyCode::Char('D') => Some(0x9),
-            KeyCode::Char('F') => Some(0xE),
-            KeyCode::Char('Z') => Some(0xA),
-            KeyCode::Char('X') => Some(0x0),
-            KeyCode::Char('C') => Some(0xB),
-            KeyCode::Char('V') => Some(0xF),
-            _ => None,
-        }
-    } else {
-        None
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::rom::Platform;
+    use chip8::scheduler::DEFAULT_IPF;
+
+    fn trace_of(program: &[u8], instructions: u64) -> String {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        chip8.load(program);
+        let mut out = Vec::new();
+        write_trace(&mut chip8, instructions, DEFAULT_IPF, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn trace_wraps_around_the_end_of_memory() {
+        // JP 0xFFF, where the second byte is the first of the font
+        let trace = trace_of(&[0x1F, 0xFF], 2);
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("200  1FFF"), "{}", lines[0]);
+        assert!(lines[1].starts_with("FFF  00F0"), "{}", lines[1]);
     }
 }
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviour that differs between the interpreters chip8 programs were
+/// written for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    /// 8xy1, 8xy2 and 8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Fx55 and Fx65 leave I pointing past the last register they touched.
+    pub memory_increment: bool,
+    /// 8xy6 and 8xyE shift Vx in place instead of shifting Vy into Vx.
+    pub shift_vx: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around.
+    pub clipping: bool,
+    /// Bnnn jumps to nnn + Vx, with x the highest nibble of nnn, instead of nnn + V0.
+    pub jump_vx: bool,
+    /// Dxyn waits for the display to refresh, which ends the frame, so at
+    /// most one sprite gets drawn per frame.
+    pub display_wait: bool,
+    /// How many calls deep a program can go before the stack overflows.
+    pub stack_depth: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuirkPreset {
+    Vip,
+    Schip,
+    XoChip,
+}
+
+const PRESETS: [QuirkPreset; 3] = [QuirkPreset::Vip, QuirkPreset::Schip, QuirkPreset::XoChip];
+
+impl Quirks {
+    /// The preset name, or "custom" for a mix that doesn't match one.
+    pub fn name(&self) -> &'static str {
+        PRESETS
+            .iter()
+            .find(|preset| preset.quirks() == *self)
+            .map_or("custom", |preset| preset.name())
+    }
+}
+
+impl QuirkPreset {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            QuirkPreset::Vip => Quirks {
+                vf_reset: true,
+                memory_increment: true,
+                shift_vx: false,
+                clipping: true,
+                jump_vx: false,
+                display_wait: true,
+                stack_depth: 12,
+            },
+            QuirkPreset::Schip => Quirks {
+                vf_reset: false,
+                memory_increment: false,
+                shift_vx: true,
+                clipping: true,
+                jump_vx: true,
+                display_wait: false,
+                stack_depth: 16,
+            },
+            QuirkPreset::XoChip => Quirks {
+                vf_reset: false,
+                memory_increment: true,
+                shift_vx: false,
+                clipping: false,
+                jump_vx: false,
+                display_wait: false,
+                stack_depth: 16,
+            },
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            QuirkPreset::Vip => "vip",
+            QuirkPreset::Schip => "schip",
+            QuirkPreset::XoChip => "xo-chip",
+        }
+    }
+}
+
+impl fmt::Display for QuirkPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for QuirkPreset {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vip" => Ok(QuirkPreset::Vip),
+            "schip" => Ok(QuirkPreset::Schip),
+            "xo-chip" => Ok(QuirkPreset::XoChip),
+            _ => Err(format!(
+                "unknown quirk preset '{}', expected vip, schip or xo-chip",
+                s
+            )),
+        }
+    }
+}
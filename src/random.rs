@@ -0,0 +1,52 @@
+//! Where CXNN gets its numbers from. The generator is behind a trait so a
+//! different one, e.g. a copy of an original interpreter's routine, can be
+//! dropped in, and its state goes into save states.
+
+/// A source of random bytes whose state can be saved and restored.
+pub trait Random {
+    fn byte(&mut self) -> u8;
+    fn state(&self) -> Vec<u8>;
+    /// Fails without changing anything when `state` didn't come from the
+    /// same kind of generator.
+    fn restore(&mut self, state: &[u8]) -> Result<(), String>;
+}
+
+/// xorshift64*, small and plenty random for games.
+pub struct Xorshift(u64);
+
+impl Xorshift {
+    /// The same seed always gives the same numbers.
+    pub fn seeded(seed: u64) -> Xorshift {
+        // splitmix64 spreads small seeds over the whole state and never
+        // leaves it at 0, where xorshift would get stuck
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Xorshift((z ^ (z >> 31)).max(1))
+    }
+
+    pub fn from_entropy() -> Xorshift {
+        Xorshift::seeded(rand::random())
+    }
+}
+
+impl Random for Xorshift {
+    fn byte(&mut self) -> u8 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+    fn state(&self) -> Vec<u8> {
+        self.0.to_be_bytes().to_vec()
+    }
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        match <[u8; 8]>::try_from(state).map(u64::from_be_bytes) {
+            Ok(0) | Err(_) => Err("the random number generator's state is invalid".to_string()),
+            Ok(state) => {
+                self.0 = state;
+                Ok(())
+            }
+        }
+    }
+}
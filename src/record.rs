@@ -0,0 +1,91 @@
+use crate::render::{Palette, Screen};
+use crate::scheduler::FRAME_RATE;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// Records the screen to an animated GIF. A screen that stays the same for
+/// several frames is written once with a longer delay, so idle stretches cost
+/// nothing.
+pub struct Recorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    scale: usize,
+    last: Option<Screen>,
+    /// Frames recorded so far and how many centiseconds of them are written,
+    /// so rounding 60Hz to GIF's 100Hz delays doesn't drift.
+    frames: u64,
+    written: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, palette: &Palette, scale: usize) -> io::Result<Self> {
+        let scale = scale.max(1);
+        let colours: Vec<u8> = palette
+            .background
+            .iter()
+            .chain(palette.foreground.iter())
+            .copied()
+            .collect();
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = gif::Encoder::new(file, 64 * scale as u16, 32 * scale as u16, &colours)
+            .map_err(io::Error::other)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            encoder,
+            scale,
+            last: None,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    /// Called once for every emulated frame.
+    pub fn record(&mut self, screen: &Screen) -> io::Result<()> {
+        if self.last.as_ref() != Some(screen) {
+            self.flush()?;
+            self.last = Some(*screen);
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Writes the frame still being held. The GIF is complete once the
+    /// recorder is dropped.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(screen) = self.last else {
+            return Ok(());
+        };
+        let elapsed = (self.frames * 100).div_ceil(FRAME_RATE as u64);
+        let delay = elapsed - self.written;
+        if delay == 0 {
+            // shown for less than a centisecond, the next frame covers it
+            return Ok(());
+        }
+        self.written = elapsed;
+        let width = 64 * self.scale;
+        let mut pixels = Vec::with_capacity(width * 32 * self.scale);
+        for row in screen.iter() {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|pixel| std::iter::repeat_n(*pixel, self.scale))
+                .collect();
+            for _ in 0..self.scale {
+                pixels.extend_from_slice(&line);
+            }
+        }
+        let frame = gif::Frame {
+            width: width as u16,
+            height: (32 * self.scale) as u16,
+            delay: delay.min(u16::MAX as u64) as u16,
+            buffer: pixels.into(),
+            ..Default::default()
+        };
+        self.encoder.write_frame(&frame).map_err(io::Error::other)
+    }
+}
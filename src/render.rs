@@ -0,0 +1,520 @@
+use crossterm::{
+    cursor,
+    style::{Color, Colors, Print, ResetColor, SetColors},
+    terminal, QueueableCommand,
+};
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+pub type Screen = [[u8; 64]; 32];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            foreground: [0xFF; 3],
+            background: [0x00; 3],
+        }
+    }
+}
+
+impl Palette {
+    fn colour(&self, pixel: u8) -> [u8; 3] {
+        if pixel == 0 {
+            self.background
+        } else {
+            self.foreground
+        }
+    }
+}
+
+fn parse_rgb(s: &str) -> Option<[u8; 3]> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(s, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+impl fmt::Display for Palette {
+    /// The theme name, or the colours when no theme matches.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((name, _)) = THEMES.iter().find(|(_, palette)| palette == self) {
+            return write!(f, "{}", name);
+        }
+        let [fr, fg, fb] = self.foreground;
+        let [br, bg, bb] = self.background;
+        write!(
+            f,
+            "{:02x}{:02x}{:02x},{:02x}{:02x}{:02x}",
+            fr, fg, fb, br, bg, bb
+        )
+    }
+}
+
+/// Named palettes for `--theme`.
+pub const THEMES: [(&str, Palette); 4] = [
+    (
+        "mono",
+        Palette {
+            foreground: [0xFF, 0xFF, 0xFF],
+            background: [0x00, 0x00, 0x00],
+        },
+    ),
+    (
+        "amber",
+        Palette {
+            foreground: [0xFF, 0xB0, 0x00],
+            background: [0x1A, 0x10, 0x00],
+        },
+    ),
+    (
+        "green",
+        Palette {
+            foreground: [0x33, 0xFF, 0x66],
+            background: [0x00, 0x1A, 0x08],
+        },
+    ),
+    (
+        "lcd",
+        Palette {
+            foreground: [0x0F, 0x38, 0x0F],
+            background: [0x9B, 0xBC, 0x0F],
+        },
+    ),
+];
+
+impl FromStr for Palette {
+    type Err = String;
+    /// Parses a theme name, or "foreground,background" with both written as
+    /// RRGGBB hex.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, palette)) = THEMES.iter().find(|(name, _)| *name == s) {
+            return Ok(*palette);
+        }
+        let error = || {
+            format!(
+                "unknown theme '{}', expected mono, amber, green, lcd or colors like ffffff,000000",
+                s
+            )
+        };
+        let (foreground, background) = s.split_once(',').ok_or_else(error)?;
+        Ok(Palette {
+            foreground: parse_rgb(foreground).ok_or_else(error)?,
+            background: parse_rgb(background).ok_or_else(error)?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Text,
+    Sixel,
+    Kitty,
+}
+
+impl FromStr for RenderMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(RenderMode::Text),
+            "sixel" => Ok(RenderMode::Sixel),
+            "kitty" => Ok(RenderMode::Kitty),
+            _ => Err(format!(
+                "unknown render mode '{}', expected text, sixel or kitty",
+                s
+            )),
+        }
+    }
+}
+
+/// Something that can put the chip8 framebuffer in front of the user.
+pub trait Renderer {
+    fn draw(&mut self, screen: &Screen) -> io::Result<()>;
+    /// How many terminal rows the drawn screen covers, so nothing else gets
+    /// drawn over it.
+    fn rows(&self) -> u16;
+    fn columns(&self) -> u16;
+}
+
+/// Columns and rows covered by an image `width` x `height` pixels. Falls
+/// back to one cell per chip8 pixel when the terminal doesn't report its
+/// size in pixels.
+fn pixel_cells(width: usize, height: usize) -> (u16, u16) {
+    match terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => {
+            let cell_width = (size.width / size.columns).max(1) as usize;
+            let cell_height = (size.height / size.rows).max(1) as usize;
+            (
+                width.div_ceil(cell_width) as u16,
+                height.div_ceil(cell_height) as u16,
+            )
+        }
+        _ => (64, 32),
+    }
+}
+
+/// The screen as plain text, one line per row, for printing outside the
+/// terminal UI.
+pub fn text(screen: &Screen) -> String {
+    let mut text = String::new();
+    for row in screen {
+        let line: String = row
+            .iter()
+            .map(|pixel| if *pixel == 0 { ' ' } else { '█' })
+            .collect();
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Draws every pixel as a character cell, only touching cells that changed.
+pub struct TextRenderer<W: Write> {
+    out: W,
+    palette: Palette,
+    old_screen: Option<Screen>,
+}
+
+impl<W: Write> TextRenderer<W> {
+    pub fn new(out: W, palette: Palette) -> Self {
+        Self {
+            out,
+            palette,
+            old_screen: None,
+        }
+    }
+}
+
+impl<W: Write> Renderer for TextRenderer<W> {
+    fn draw(&mut self, screen: &Screen) -> io::Result<()> {
+        let [fr, fg, fb] = self.palette.foreground;
+        let [br, bg, bb] = self.palette.background;
+        self.out.queue(SetColors(Colors::new(
+            Color::Rgb {
+                r: fr,
+                g: fg,
+                b: fb,
+            },
+            Color::Rgb {
+                r: br,
+                g: bg,
+                b: bb,
+            },
+        )))?;
+        for (y, row) in screen.iter().enumerate() {
+            for (x, value) in row.iter().enumerate() {
+                // the first frame draws every cell so the background colour fills the screen
+                if self.old_screen.is_some_and(|old| old[y][x] == *value) {
+                    continue;
+                }
+                let pixel = if *value == 0 { ' ' } else { '#' };
+                self.out.queue(cursor::MoveTo(x as u16, y as u16))?;
+                self.out.queue(Print(pixel))?;
+            }
+        }
+        self.out.queue(ResetColor)?;
+        self.old_screen = Some(*screen);
+        self.out.flush()
+    }
+    fn rows(&self) -> u16 {
+        32
+    }
+    fn columns(&self) -> u16 {
+        64
+    }
+}
+
+/// Draws the framebuffer as real pixels using DEC sixel graphics.
+/// Every chip8 pixel becomes a `scale` x `scale` block so the image stays sharp.
+pub struct SixelRenderer<W: Write> {
+    out: W,
+    scale: usize,
+    palette: Palette,
+    old_screen: Option<Screen>,
+}
+
+impl<W: Write> SixelRenderer<W> {
+    pub fn new(out: W, scale: usize, palette: Palette) -> Self {
+        Self {
+            out,
+            scale: scale.max(1),
+            palette,
+            old_screen: None,
+        }
+    }
+}
+
+impl<W: Write> Renderer for SixelRenderer<W> {
+    fn draw(&mut self, screen: &Screen) -> io::Result<()> {
+        if self.old_screen.as_ref() == Some(screen) {
+            return Ok(());
+        }
+        self.old_screen = Some(*screen);
+        self.out.queue(cursor::MoveTo(0, 0))?;
+        self.out
+            .write_all(&sixel(screen, self.scale, &self.palette))?;
+        self.out.flush()
+    }
+    fn rows(&self) -> u16 {
+        pixel_cells(64 * self.scale, 32 * self.scale).1
+    }
+    fn columns(&self) -> u16 {
+        pixel_cells(64 * self.scale, 32 * self.scale).0
+    }
+}
+
+/// Encodes the screen as a two colour sixel image. Colour 0 is the background
+/// and colour 1 the foreground; both are written for every band so the image
+/// fully replaces whatever was drawn before.
+pub fn sixel(screen: &Screen, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = screen[0].len() * scale;
+    let height = screen.len() * scale;
+    // sixel colour registers take percentages
+    let percent = |rgb: [u8; 3]| rgb.map(|c| c as u32 * 100 / 255);
+    let [br, bg, bb] = percent(palette.background);
+    let [fr, fg, fb] = percent(palette.foreground);
+    let mut out = format!(
+        "\x1bPq\"1;1;{};{}#0;2;{};{};{}#1;2;{};{};{}",
+        width, height, br, bg, bb, fr, fg, fb
+    );
+    for band in (0..height).step_by(6) {
+        for colour in 0..2u8 {
+            out.push_str(&format!("#{}", colour));
+            let mut run: Option<(u8, usize)> = None;
+            for x in 0..width {
+                let mut bits = 0;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < height && screen[y / scale][x / scale] == colour {
+                        bits |= 1 << dy;
+                    }
+                }
+                run = match run {
+                    Some((previous, count)) if previous == bits => Some((bits, count + 1)),
+                    Some((previous, count)) => {
+                        push_sixel_run(&mut out, previous, count);
+                        Some((bits, 1))
+                    }
+                    None => Some((bits, 1)),
+                };
+            }
+            if let Some((bits, count)) = run {
+                push_sixel_run(&mut out, bits, count);
+            }
+            out.push(if colour == 0 { '$' } else { '-' });
+        }
+    }
+    out.push_str("\x1b\\");
+    out.into_bytes()
+}
+
+fn push_sixel_run(out: &mut String, bits: u8, count: usize) {
+    let character = (0x3F + bits) as char;
+    if count > 3 {
+        out.push_str(&format!("!{}{}", count, character));
+    } else {
+        for _ in 0..count {
+            out.push(character);
+        }
+    }
+}
+
+/// Draws the framebuffer as real pixels using the kitty graphics protocol.
+pub struct KittyRenderer<W: Write> {
+    out: W,
+    scale: usize,
+    palette: Palette,
+    old_screen: Option<Screen>,
+}
+
+impl<W: Write> KittyRenderer<W> {
+    pub fn new(out: W, scale: usize, palette: Palette) -> Self {
+        Self {
+            out,
+            scale: scale.max(1),
+            palette,
+            old_screen: None,
+        }
+    }
+}
+
+impl<W: Write> Renderer for KittyRenderer<W> {
+    fn draw(&mut self, screen: &Screen) -> io::Result<()> {
+        if self.old_screen.as_ref() == Some(screen) {
+            return Ok(());
+        }
+        self.old_screen = Some(*screen);
+        self.out.queue(cursor::MoveTo(0, 0))?;
+        self.out
+            .write_all(&kitty(screen, self.scale, &self.palette))?;
+        self.out.flush()
+    }
+    fn rows(&self) -> u16 {
+        pixel_cells(64 * self.scale, 32 * self.scale).1
+    }
+    fn columns(&self) -> u16 {
+        pixel_cells(64 * self.scale, 32 * self.scale).0
+    }
+}
+
+/// Encodes the screen as an RGB kitty image. The image always uses id 1 so
+/// each frame replaces the previous one instead of stacking up, and `q=2`
+/// keeps the terminal from answering every frame.
+pub fn kitty(screen: &Screen, scale: usize, palette: &Palette) -> Vec<u8> {
+    let width = screen[0].len() * scale;
+    let height = screen.len() * scale;
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            rgb.extend_from_slice(&palette.colour(screen[y / scale][x / scale]));
+        }
+    }
+    let payload = base64(&rgb);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(4096).collect();
+    let mut out = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        if i == 0 {
+            out.extend_from_slice(
+                format!(
+                    "\x1b_Ga=T,f=24,i=1,q=2,C=1,s={},v={},m={};",
+                    width, height, more
+                )
+                .as_bytes(),
+            );
+        } else {
+            out.extend_from_slice(format!("\x1b_Gm={};", more).as_bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        out.push(ALPHABET[(n >> 18) as usize & 63] as char);
+        out.push(ALPHABET[(n >> 12) as usize & 63] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(n >> 6) as usize & 63] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[n as usize & 63] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn amber() -> Palette {
+        "amber".parse().unwrap()
+    }
+
+    /// Only the top left pixel is on.
+    fn corner() -> Screen {
+        let mut screen = [[0; 64]; 32];
+        screen[0][0] = 1;
+        screen
+    }
+
+    #[test]
+    fn sixel_snapshot() {
+        let sixel = String::from_utf8(sixel(&corner(), 1, &amber())).unwrap();
+        let mut expected = String::new();
+        // raster size, then background and foreground as percentages
+        expected.push_str("\x1bPq\"1;1;64;32#0;2;10;6;0#1;2;100;69;0");
+        // the first band has the pixel in its top row
+        expected.push_str("#0}!63~$#1@!63?-");
+        for _ in 0..4 {
+            expected.push_str("#0!64~$#1!64?-");
+        }
+        // the last band only has two rows
+        expected.push_str("#0!64B$#1!64?-");
+        expected.push_str("\x1b\\");
+        assert_eq!(sixel, expected);
+    }
+
+    #[test]
+    fn sixel_scales_pixels_into_blocks() {
+        let sixel = String::from_utf8(sixel(&corner(), 2, &amber())).unwrap();
+        assert!(sixel.starts_with("\x1bPq\"1;1;128;64#"));
+        // the pixel covers the top two rows of the first two columns
+        assert!(sixel.contains("#0{{!126~$#1BB!126?-"));
+        assert!(sixel.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn kitty_splits_the_payload_into_chunks() {
+        let kitty = String::from_utf8(kitty(&corner(), 2, &Palette::default())).unwrap();
+        let chunks: Vec<&str> = kitty
+            .strip_suffix("\x1b\\")
+            .unwrap()
+            .split("\x1b\\")
+            .collect();
+        // 128x64 RGB pixels are 32768 base64 characters
+        assert_eq!(chunks.len(), 8);
+        assert_eq!(
+            &chunks[0][..chunks[0].find(';').unwrap() + 1],
+            "\x1b_Ga=T,f=24,i=1,q=2,C=1,s=128,v=64,m=1;"
+        );
+        for chunk in &chunks[1..7] {
+            assert!(chunk.starts_with("\x1b_Gm=1;"));
+        }
+        assert!(chunks[7].starts_with("\x1b_Gm=0;"));
+        let payload: String = chunks
+            .iter()
+            .map(|chunk| {
+                let data = &chunk[chunk.find(';').unwrap() + 1..];
+                assert_eq!(data.len(), 4096);
+                data
+            })
+            .collect();
+        // the first two pixels of the first row are on, then it's background
+        assert!(payload.starts_with("////////AAAA"));
+        assert!(payload[12..].trim_start_matches('A').starts_with("////"));
+    }
+
+    #[test]
+    fn kitty_marks_the_last_chunk() {
+        let mut screen = [[0; 64]; 32];
+        screen[31][63] = 1;
+        let kitty = String::from_utf8(kitty(&screen, 1, &amber())).unwrap();
+        // 6144 bytes of RGB are exactly two chunks
+        assert_eq!(kitty.matches("\x1b\\").count(), 2);
+        assert!(kitty.starts_with("\x1b_Ga=T,f=24,i=1,q=2,C=1,s=64,v=32,m=1;GhAA"));
+        assert!(kitty.contains("\x1b\\\x1b_Gm=0;GhAA"));
+        // the bottom right pixel is the last one sent
+        assert!(kitty.ends_with("GhAA/7AA\x1b\\"));
+    }
+
+    #[test]
+    fn base64_pads_the_last_group() {
+        assert_eq!(base64(b"Man"), "TWFu");
+        assert_eq!(base64(b"Ma"), "TWE=");
+        assert_eq!(base64(b"M"), "TQ==");
+        assert_eq!(base64(b""), "");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::cartridge::{self, Cartridge};
+use crate::database::{self, Entry};
+use crate::keymap::Keymap;
+use crate::quirks::{QuirkPreset, Quirks};
+use crate::render::Palette;
+use crate::scheduler::DEFAULT_IPF;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Programs are loaded here; everything below belongs to the interpreter.
+pub const PROGRAM_START: usize = 0x200;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::Schip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - PROGRAM_START
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "CHIP-8"),
+            Platform::Schip => write!(f, "SCHIP"),
+            Platform::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::Schip),
+            "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform '{}', expected chip8, schip or xo-chip",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(PathBuf, io::Error),
+    Empty(PathBuf),
+    Cartridge(PathBuf, String),
+    TooLarge {
+        path: PathBuf,
+        size: usize,
+        platform: Platform,
+    },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(path, error) => write!(f, "couldn't read {}: {}", path.display(), error),
+            RomError::Empty(path) => write!(f, "{} is empty", path.display()),
+            RomError::Cartridge(path, error) => write!(f, "{}: {}", path.display(), error),
+            RomError::TooLarge {
+                path,
+                size,
+                platform,
+            } => write!(
+                f,
+                "{} is {} bytes but {} programs can be at most {} bytes",
+                path.display(),
+                size,
+                platform,
+                platform.max_rom_size()
+            ),
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RomError::Io(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// How a rom is run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    pub ipf: u32,
+    pub quirks: Quirks,
+    pub keymap: Keymap,
+    pub palette: Palette,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ipf: DEFAULT_IPF,
+            quirks: QuirkPreset::Vip.quirks(),
+            keymap: Keymap::default(),
+            palette: Palette::default(),
+        }
+    }
+}
+
+pub struct Rom {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+    pub sha1: String,
+    pub crc32: u32,
+    pub platform: Platform,
+    /// What the database knows about this rom, if anything.
+    pub entry: Option<&'static Entry>,
+    /// Settings that came with an Octo cartridge.
+    pub cartridge: Option<Cartridge>,
+    /// Things that look wrong but don't stop the rom from running.
+    pub warnings: Vec<String>,
+}
+
+impl Rom {
+    /// Loads and identifies a rom, unpacking it first if it is an Octo
+    /// cartridge. The platform is taken from `platform` when given, otherwise
+    /// from the cartridge or the database, and defaults to plain CHIP-8.
+    pub fn load(path: impl AsRef<Path>, platform: Option<Platform>) -> Result<Rom, RomError> {
+        let path = path.as_ref().to_path_buf();
+        let bytes = fs::read(&path).map_err(|error| RomError::Io(path.clone(), error))?;
+        Rom::from_bytes(path, bytes, platform)
+    }
+
+    pub fn from_bytes(
+        path: PathBuf,
+        bytes: Vec<u8>,
+        platform: Option<Platform>,
+    ) -> Result<Rom, RomError> {
+        let (bytes, cartridge) = if cartridge::is_gif(&bytes) {
+            let mut cartridge = cartridge::decode(&bytes)
+                .map_err(|error| RomError::Cartridge(path.clone(), error))?;
+            (std::mem::take(&mut cartridge.program), Some(cartridge))
+        } else {
+            (bytes, None)
+        };
+        if bytes.is_empty() {
+            return Err(RomError::Empty(path));
+        }
+        let sha1 = sha1_smol::Sha1::from(&bytes).digest().to_string();
+        let entry = database::lookup(&sha1);
+        let known = match cartridge.as_ref().and_then(|cartridge| cartridge.platform) {
+            Some(platform) => Some(("the cartridge", platform)),
+            None => entry.map(|entry| ("the database", entry.platform)),
+        };
+        let mut warnings = Vec::new();
+        if let (Some(given), Some((source, known))) = (platform, known) {
+            if given != known {
+                warnings.push(format!(
+                    "{} says this is a {} program, running it as {}",
+                    source, known, given
+                ));
+            }
+        }
+        let platform = platform
+            .or(known.map(|(_, platform)| platform))
+            .unwrap_or(Platform::Chip8);
+        if bytes.len() > platform.max_rom_size() {
+            return Err(RomError::TooLarge {
+                path,
+                size: bytes.len(),
+                platform,
+            });
+        }
+        if !bytes.len().is_multiple_of(2) {
+            warnings.push(format!(
+                "odd length ({} bytes), the last instruction is incomplete",
+                bytes.len()
+            ));
+        }
+        if bytes.iter().all(|byte| *byte == 0) {
+            warnings.push("the rom only contains zeroes".to_string());
+        }
+        Ok(Rom {
+            sha1,
+            crc32: crc32fast::hash(&bytes),
+            platform,
+            entry,
+            cartridge,
+            path,
+            bytes,
+            warnings,
+        })
+    }
+
+    /// The settings this rom asks for: the cartridge's first, then the
+    /// database's, with `defaults` for anything neither of them knows.
+    pub fn settings(&self, defaults: Settings) -> Settings {
+        let cartridge = self.cartridge.as_ref();
+        let entry = self.entry;
+        Settings {
+            ipf: cartridge
+                .and_then(|cartridge| cartridge.ipf)
+                .or(entry.map(|entry| entry.ipf))
+                .unwrap_or(defaults.ipf),
+            quirks: cartridge
+                .and_then(|cartridge| cartridge.quirks)
+                .or(entry.map(|entry| entry.quirks.quirks()))
+                .unwrap_or(defaults.quirks),
+            keymap: entry
+                .and_then(|entry| entry.keymap)
+                .unwrap_or(defaults.keymap),
+            palette: cartridge
+                .and_then(|cartridge| cartridge.palette)
+                .or(entry.and_then(|entry| entry.palette))
+                .unwrap_or(defaults.palette),
+        }
+    }
+
+    /// The database title when known, the file name otherwise.
+    pub fn name(&self) -> String {
+        if let Some(entry) = self.entry {
+            return entry.title.to_string();
+        }
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled(file: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join(file)
+    }
+
+    fn error(bytes: Vec<u8>, platform: Option<Platform>) -> String {
+        match Rom::from_bytes(PathBuf::from("game.ch8"), bytes, platform) {
+            Ok(_) => panic!("the rom loaded"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    fn warnings(bytes: Vec<u8>) -> Vec<String> {
+        Rom::from_bytes(PathBuf::from("game.ch8"), bytes, None)
+            .unwrap()
+            .warnings
+    }
+
+    #[test]
+    fn unreadable_roms_report_the_io_error() {
+        let path = bundled("missing.ch8");
+        let error = Rom::load(&path, None).err().unwrap();
+        assert!(
+            matches!(&error, RomError::Io(_, error) if error.kind() == io::ErrorKind::NotFound)
+        );
+        assert!(error
+            .to_string()
+            .starts_with(&format!("couldn't read {}: ", path.display())));
+    }
+
+    #[test]
+    fn empty_roms_are_rejected() {
+        assert_eq!(error(Vec::new(), None), "game.ch8 is empty");
+    }
+
+    #[test]
+    fn roms_must_fit_the_platforms_memory() {
+        assert_eq!(
+            error(vec![0x12; 0xE02], None),
+            "game.ch8 is 3586 bytes but CHIP-8 programs can be at most 3584 bytes"
+        );
+        assert_eq!(
+            error(vec![0x12; 0x10000], Some(Platform::XoChip)),
+            "game.ch8 is 65536 bytes but XO-CHIP programs can be at most 65024 bytes"
+        );
+        let rom = Rom::from_bytes(
+            PathBuf::from("game.ch8"),
+            vec![0x12; 0xE02],
+            Some(Platform::XoChip),
+        );
+        assert!(rom.is_ok());
+    }
+
+    #[test]
+    fn odd_lengths_and_zeroes_are_warned_about() {
+        assert!(warnings(vec![0x12, 0x00]).is_empty());
+        assert_eq!(
+            warnings(vec![0x12, 0x00, 0x00]),
+            ["odd length (3 bytes), the last instruction is incomplete"]
+        );
+        assert_eq!(warnings(vec![0; 4]), ["the rom only contains zeroes"]);
+    }
+
+    #[test]
+    fn settings_come_from_the_database_before_the_defaults() {
+        let defaults = Settings {
+            ipf: 7,
+            ..Settings::default()
+        };
+        let unknown = Rom::from_bytes(PathBuf::from("game.ch8"), vec![0x12, 0x00], None).unwrap();
+        assert_eq!(unknown.settings(defaults), defaults);
+        let tetris = Rom::load(bundled("tetris.rom"), None).unwrap();
+        let settings = tetris.settings(defaults);
+        assert_eq!(settings.ipf, 10);
+        assert_eq!(settings.quirks, QuirkPreset::Vip.quirks());
+        assert_eq!(settings.keymap, crate::keymap::QWERTY_KEYMAP);
+        assert_eq!(settings.palette, defaults.palette);
+    }
+
+    #[test]
+    fn overriding_the_known_platform_is_warned_about() {
+        let scrolling = bundled("8-scrolling.ch8");
+        let rom = Rom::load(&scrolling, None).unwrap();
+        assert_eq!(rom.platform, Platform::Schip);
+        assert!(rom.warnings.is_empty());
+        let rom = Rom::load(&scrolling, Some(Platform::Chip8)).unwrap();
+        assert_eq!(rom.platform, Platform::Chip8);
+        assert_eq!(
+            rom.warnings,
+            ["the database says this is a SCHIP program, running it as CHIP-8"]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::frontend::Clock;
+use crate::hotkeys::Action;
+use crate::timing::Timing;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_IPF: u32 = 15;
+
+/// How fast emulated frames run compared to the real 60Hz.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    Slow(u32),
+    Normal,
+    Fast(u32),
+    Uncapped,
+}
+
+const SPEEDS: [Speed; 8] = [
+    Speed::Slow(8),
+    Speed::Slow(4),
+    Speed::Slow(2),
+    Speed::Normal,
+    Speed::Fast(2),
+    Speed::Fast(4),
+    Speed::Fast(8),
+    Speed::Uncapped,
+];
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Speed::Slow(divisor) => write!(f, "1/{}x", divisor),
+            Speed::Normal => write!(f, "1x"),
+            Speed::Fast(multiple) => write!(f, "{}x", multiple),
+            Speed::Uncapped => write!(f, "uncapped"),
+        }
+    }
+}
+
+/// Decides when the next frame runs and how many instructions it gets.
+///
+/// Hotkeys pause, advance a single frame while paused, step through
+/// fast-forward and slow motion, and go back to normal speed.
+pub struct Scheduler {
+    pub ipf: u32,
+    /// With VIP timing `ipf` is ignored and machine cycles decide instead.
+    pub timing: Timing,
+    pub speed: Speed,
+    pub paused: bool,
+    advance: bool,
+    next_frame: Instant,
+    /// Frames and instructions per second, measured over the last second.
+    pub fps: f64,
+    pub ips: f64,
+    measure_start: Instant,
+    frames: u32,
+    instructions: u64,
+    clock: Box<dyn Clock>,
+}
+
+impl Scheduler {
+    pub fn new(ipf: u32, clock: Box<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            ipf,
+            timing: Timing::Ipf,
+            speed: Speed::Normal,
+            paused: false,
+            advance: false,
+            next_frame: now,
+            fps: 0.0,
+            ips: 0.0,
+            measure_start: now,
+            frames: 0,
+            instructions: 0,
+            clock,
+        }
+    }
+
+    /// Returns true when the action was about speed and got handled here.
+    pub fn handle(&mut self, action: Action) -> bool {
+        let index = SPEEDS.iter().position(|speed| *speed == self.speed);
+        match action {
+            Action::Pause => self.paused = !self.paused,
+            Action::Advance => self.advance = self.paused,
+            Action::Faster => {
+                if let Some(faster) = index.and_then(|i| SPEEDS.get(i + 1)) {
+                    self.speed = *faster;
+                }
+            }
+            Action::Slower => {
+                if let Some(slower) = index.and_then(|i| i.checked_sub(1)) {
+                    self.speed = SPEEDS[slower];
+                }
+            }
+            Action::NormalSpeed => {
+                self.speed = Speed::Normal;
+                self.paused = false;
+            }
+            _ => return false,
+        }
+        self.next_frame = self.clock.now();
+        true
+    }
+
+    /// Whether a frame should be emulated now. While paused only a requested
+    /// frame advance lets one through.
+    pub fn run_frame(&mut self) -> bool {
+        if !self.paused {
+            return true;
+        }
+        std::mem::take(&mut self.advance)
+    }
+
+    pub fn record_frame(&mut self, instructions: u32) {
+        self.frames += 1;
+        self.instructions += instructions as u64;
+    }
+
+    fn measure(&mut self) {
+        let elapsed = self.clock.now() - self.measure_start;
+        if elapsed < Duration::from_secs(1) {
+            return;
+        }
+        self.fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.ips = self.instructions as f64 / elapsed.as_secs_f64();
+        self.frames = 0;
+        self.instructions = 0;
+        self.measure_start = self.clock.now();
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        let frame = Duration::from_secs(1) / FRAME_RATE;
+        match self.speed {
+            Speed::Slow(divisor) => frame * divisor,
+            Speed::Normal => frame,
+            Speed::Fast(multiple) => frame / multiple,
+            Speed::Uncapped => Duration::ZERO,
+        }
+    }
+
+    /// Sleeps until the next frame is due. Paused emulation still wakes up at
+    /// 60Hz so input stays responsive.
+    pub fn wait(&mut self) {
+        self.measure();
+        let duration = if self.paused {
+            Duration::from_secs(1) / FRAME_RATE
+        } else {
+            self.frame_duration()
+        };
+        let now = self.clock.now();
+        self.next_frame += duration;
+        // don't try to catch up after falling far behind, e.g. after being suspended
+        if self.next_frame + duration < now {
+            self.next_frame = now;
+        }
+        self.clock
+            .sleep(self.next_frame.saturating_duration_since(now));
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    pub fn status(&self) -> String {
+        let state = if self.paused {
+            "paused".to_string()
+        } else {
+            self.speed.to_string()
+        };
+        match self.timing {
+            Timing::Ipf => format!("{} ipf | {}", self.ipf, state),
+            Timing::Vip => format!("vip timing | {}", state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::FakeClock;
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(DEFAULT_IPF, Box::new(FakeClock::default()))
+    }
+
+    #[test]
+    fn advance_runs_one_frame_while_paused() {
+        let mut scheduler = scheduler();
+        scheduler.handle(Action::Pause);
+        assert!(!scheduler.run_frame());
+        scheduler.handle(Action::Advance);
+        assert!(scheduler.run_frame());
+        assert!(!scheduler.run_frame());
+    }
+
+    #[test]
+    fn advance_while_running_is_a_no_op() {
+        let mut scheduler = scheduler();
+        scheduler.handle(Action::Advance);
+        assert!(scheduler.run_frame());
+        scheduler.handle(Action::Pause);
+        assert!(!scheduler.run_frame());
+    }
+}
@@ -0,0 +1,83 @@
+//! The call stack: return addresses, as many as the platform had room for.
+
+use std::error;
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StackError {
+    /// A call with every level already taken.
+    Overflow,
+    /// A return with no call to return from.
+    Underflow,
+}
+
+impl fmt::Display for StackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StackError::Overflow => write!(f, "stack overflow"),
+            StackError::Underflow => write!(f, "return without a call"),
+        }
+    }
+}
+
+impl error::Error for StackError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stack {
+    /// The outermost call first.
+    addresses: Vec<u16>,
+    depth: usize,
+}
+
+impl Stack {
+    pub fn new(depth: usize) -> Stack {
+        Stack {
+            addresses: Vec::with_capacity(depth),
+            depth,
+        }
+    }
+
+    pub fn push(&mut self, address: u16) -> Result<(), StackError> {
+        if self.addresses.len() == self.depth {
+            return Err(StackError::Overflow);
+        }
+        self.addresses.push(address);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16, StackError> {
+        self.addresses.pop().ok_or(StackError::Underflow)
+    }
+
+    /// How many calls deep the program is.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// How many calls deep a program can go.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The return addresses, the outermost call first and the one `pop`
+    /// returns last.
+    pub fn addresses(&self) -> &[u16] {
+        &self.addresses
+    }
+}
+
+/// The return addresses in hex, outermost first, for a call stack view.
+impl fmt::Display for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addresses: Vec<String> = self
+            .addresses
+            .iter()
+            .map(|address| format!("{:03X}", address))
+            .collect();
+        write!(f, "{}", addresses.join(" "))
+    }
+}
@@ -0,0 +1,204 @@
+//! Save states: a snapshot of everything a running program can observe,
+//! kept in one file per rom named after its SHA-1.
+
+use crate::stack::Stack;
+use crate::Chip8;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 3;
+
+pub fn path(dir: &Path, sha1: &str) -> PathBuf {
+    dir.join(format!("{}.state", sha1))
+}
+
+pub fn save(chip8: &Chip8) -> Vec<u8> {
+    let timers = chip8.timers.lock().unwrap();
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&chip8.program_counter.to_be_bytes());
+    bytes.extend_from_slice(&chip8.i_register.to_be_bytes());
+    bytes.extend_from_slice(&chip8.registers);
+    bytes.push(chip8.stack.len() as u8);
+    for address in chip8.stack.addresses() {
+        bytes.extend_from_slice(&address.to_be_bytes());
+    }
+    bytes.push(timers.delay_timer);
+    bytes.push(timers.sound_timer);
+    bytes.push(timers.pitch);
+    match timers.audio_pattern {
+        Some(pattern) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&pattern);
+        }
+        None => bytes.push(0),
+    }
+    for row in chip8.screen {
+        bytes.extend_from_slice(&row);
+    }
+    let memory = chip8.bus().memory();
+    bytes.extend_from_slice(&(memory.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(memory);
+    let rng = chip8.rng.state();
+    bytes.push(rng.len() as u8);
+    bytes.extend_from_slice(&rng);
+    bytes
+}
+
+/// Restores a state written by `save`. Nothing changes when the state is
+/// damaged or was saved on a platform with a different memory size.
+pub fn load(chip8: &mut Chip8, bytes: &[u8]) -> Result<(), String> {
+    let mut reader = Reader(bytes);
+    if reader.take(4)? != MAGIC {
+        return Err("not a save state".to_string());
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(format!("save state version {} isn't supported", version));
+    }
+    let program_counter = reader.word()?;
+    let i_register = reader.word()?;
+    let registers: [u8; 16] = reader.take(16)?.try_into().unwrap();
+    let length = reader.byte()?;
+    let mut addresses = Vec::new();
+    for _ in 0..length {
+        addresses.push(reader.word()?);
+    }
+    let mut stack = Stack::new(chip8.stack.depth());
+    for address in addresses {
+        stack.push(address).map_err(|_| {
+            format!(
+                "the state is more than the {} calls deep this machine allows",
+                stack.depth()
+            )
+        })?;
+    }
+    let delay_timer = reader.byte()?;
+    let sound_timer = reader.byte()?;
+    let pitch = reader.byte()?;
+    let audio_pattern = match reader.byte()? {
+        0 => None,
+        _ => Some(reader.take(16)?.try_into().unwrap()),
+    };
+    let mut screen = [[0; 64]; 32];
+    for row in screen.iter_mut() {
+        row.copy_from_slice(reader.take(64)?);
+    }
+    let memory_size = u32::from_be_bytes(reader.take(4)?.try_into().unwrap()) as usize;
+    if memory_size != chip8.bus().memory().len() {
+        return Err(format!(
+            "the state has {} bytes of memory but this machine has {}",
+            memory_size,
+            chip8.bus().memory().len()
+        ));
+    }
+    let memory = reader.take(memory_size)?;
+    let length = reader.byte()? as usize;
+    chip8.rng.restore(reader.take(length)?)?;
+
+    chip8.program_counter = program_counter;
+    chip8.i_register = i_register;
+    chip8.registers = registers;
+    chip8.stack = stack;
+    chip8.screen = screen;
+    chip8.bus_mut().memory_mut().copy_from_slice(memory);
+    chip8.waiting_for_key = false;
+    chip8.waiting_for_display = false;
+    chip8.cycles = 0;
+    chip8.fault = None;
+    chip8.key = None;
+    let mut timers = chip8.timers.lock().unwrap();
+    timers.delay_timer = delay_timer;
+    timers.sound_timer = sound_timer;
+    timers.pitch = pitch;
+    timers.audio_pattern = audio_pattern;
+    Ok(())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.0.len() < n {
+            return Err("save state is cut short".to_string());
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+    fn word(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::QuirkPreset;
+    use crate::rom::Platform;
+
+    #[test]
+    fn loaded_states_draw_the_same_random_numbers() {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        // RND V0, 0xFF; JP 0x200
+        chip8.load(&[0xC0, 0xFF, 0x12, 0x00]);
+        let state = save(&chip8);
+        let draw = |chip8: &mut Chip8| -> Vec<u8> {
+            (0..8)
+                .map(|_| {
+                    crate::step(chip8);
+                    crate::step(chip8);
+                    chip8.registers[0]
+                })
+                .collect()
+        };
+        let first = draw(&mut chip8);
+        load(&mut chip8, &state).unwrap();
+        assert_eq!(draw(&mut chip8), first);
+    }
+
+    #[test]
+    fn states_keep_the_call_stack() {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        // CALL 0x202; CALL 0x204; JP 0x204
+        chip8.load(&[0x22, 0x02, 0x22, 0x04, 0x12, 0x04]);
+        crate::step(&mut chip8);
+        crate::step(&mut chip8);
+        let state = save(&chip8);
+        let mut loaded = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        load(&mut loaded, &state).unwrap();
+        assert_eq!(loaded.stack().addresses(), [0x200, 0x202]);
+    }
+
+    #[test]
+    fn loading_ends_a_display_wait_and_the_frames_cycles() {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        // DRW V0, V0, 1; JP 0x202
+        chip8.load(&[0xD0, 0x01, 0x12, 0x02]);
+        let state = save(&chip8);
+        crate::run_cycles(&mut chip8);
+        assert!(chip8.waiting_for_display());
+        // as if the sprite had run past the end of the frame
+        chip8.cycles = -1000;
+        load(&mut chip8, &state).unwrap();
+        assert!(!chip8.waiting_for_display());
+        assert_eq!(chip8.cycles, 0);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        let mut state = save(&chip8);
+        for version in [1, 2, VERSION + 1] {
+            state[MAGIC.len()] = version;
+            let mut loaded = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+            assert_eq!(
+                load(&mut loaded, &state),
+                Err(format!("save state version {} isn't supported", version))
+            );
+        }
+    }
+}
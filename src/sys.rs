@@ -0,0 +1,45 @@
+//! 0NNN, which on the COSMAC VIP ran the machine code at NNN. Nothing here
+//! runs 1802 code, but a native handler can stand in for a routine a rom
+//! relies on, and the rest can be ignored or stop the program.
+
+use crate::Chip8;
+use std::fmt;
+use std::str::FromStr;
+
+/// Stands in for the machine code routine at the address it's registered
+/// for. The program goes on past the 0NNN afterwards, as if the routine had
+/// returned.
+pub type Handler = Box<dyn FnMut(&mut Chip8)>;
+
+/// What 0NNN does when no handler is registered for NNN.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Sys {
+    /// Nothing, like most interpreters after the VIP.
+    #[default]
+    Ignore,
+    /// Stops the program with `Fault::Sys`.
+    Error,
+}
+
+impl fmt::Display for Sys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Sys::Ignore => write!(f, "ignore"),
+            Sys::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for Sys {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Sys::Ignore),
+            "error" => Ok(Sys::Error),
+            _ => Err(format!(
+                "unknown 0NNN handling '{}', expected ignore or error",
+                s
+            )),
+        }
+    }
+}
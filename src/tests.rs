@@ -0,0 +1,937 @@
+//! One instruction at a time: `machine` builds a Chip8 in a given state,
+//! `run` executes a single opcode on it and the outcome is everything that
+//! changed, which tests compare against a list of `Change`s.
+
+use super::*;
+use crate::quirks::QuirkPreset;
+use std::cell::Cell;
+use std::rc::Rc;
+use Change::*;
+
+const PRESETS: [QuirkPreset; 3] = [QuirkPreset::Vip, QuirkPreset::Schip, QuirkPreset::XoChip];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Change {
+    V(usize, u8),
+    I(u16),
+    /// Only when the program counter didn't just move on to the next instruction.
+    Pc(u16),
+    /// How many calls deep the program is.
+    Sp(usize),
+    /// A return address and its slot, the first call goes in slot 0.
+    Stack(usize, u16),
+    Memory(u16, u8),
+    Pixel(usize, usize, bool),
+    Delay(u8),
+    Sound(u8),
+    Pattern([u8; 16]),
+    Pitch(u8),
+    Key(Option<u8>),
+    Waiting(bool),
+    /// The program stopped.
+    Stopped(crate::Fault),
+}
+
+struct Snapshot {
+    registers: [u8; 16],
+    i_register: u16,
+    program_counter: u16,
+    stack: Vec<u16>,
+    memory: Vec<u8>,
+    screen: [[u8; 64]; 32],
+    delay_timer: u8,
+    sound_timer: u8,
+    audio_pattern: Option<[u8; 16]>,
+    pitch: u8,
+    key: Option<u8>,
+    waiting_for_key: bool,
+    fault: Option<crate::Fault>,
+}
+
+impl Snapshot {
+    fn of(chip8: &Chip8) -> Snapshot {
+        let timers = chip8.timers.lock().unwrap();
+        Snapshot {
+            registers: chip8.registers,
+            i_register: chip8.i_register,
+            program_counter: chip8.program_counter,
+            stack: chip8.stack.addresses().to_vec(),
+            memory: chip8.bus.memory().to_vec(),
+            screen: chip8.screen,
+            delay_timer: timers.delay_timer,
+            sound_timer: timers.sound_timer,
+            audio_pattern: timers.audio_pattern,
+            pitch: timers.pitch,
+            key: chip8.key,
+            waiting_for_key: chip8.waiting_for_key,
+            fault: chip8.fault,
+        }
+    }
+
+    fn changes(&self, after: &Snapshot) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (x, (&before, &after)) in self.registers.iter().zip(&after.registers).enumerate() {
+            if before != after {
+                changes.push(V(x, after));
+            }
+        }
+        if self.i_register != after.i_register {
+            changes.push(I(after.i_register));
+        }
+        if self.program_counter.wrapping_add(2) != after.program_counter {
+            changes.push(Pc(after.program_counter));
+        }
+        if self.stack.len() != after.stack.len() {
+            changes.push(Sp(after.stack.len()));
+        }
+        for (slot, &address) in after.stack.iter().enumerate() {
+            if self.stack.get(slot) != Some(&address) {
+                changes.push(Stack(slot, address));
+            }
+        }
+        for (address, (&before, &after)) in self.memory.iter().zip(&after.memory).enumerate() {
+            if before != after {
+                changes.push(Memory(address as u16, after));
+            }
+        }
+        for (y, (before, after)) in self.screen.iter().zip(&after.screen).enumerate() {
+            for (x, (&before, &after)) in before.iter().zip(after).enumerate() {
+                if before != after {
+                    changes.push(Pixel(x, y, after == 1));
+                }
+            }
+        }
+        if self.delay_timer != after.delay_timer {
+            changes.push(Delay(after.delay_timer));
+        }
+        if self.sound_timer != after.sound_timer {
+            changes.push(Sound(after.sound_timer));
+        }
+        if let (true, Some(pattern)) = (
+            self.audio_pattern != after.audio_pattern,
+            after.audio_pattern,
+        ) {
+            changes.push(Pattern(pattern));
+        }
+        if self.pitch != after.pitch {
+            changes.push(Pitch(after.pitch));
+        }
+        if self.key != after.key {
+            changes.push(Key(after.key));
+        }
+        if self.waiting_for_key != after.waiting_for_key {
+            changes.push(Waiting(after.waiting_for_key));
+        }
+        if let (true, Some(fault)) = (self.fault != after.fault, after.fault) {
+            changes.push(Stopped(fault));
+        }
+        changes
+    }
+}
+
+/// A machine with the font loaded, seeded with 0 and about to run whatever
+/// is at 0x200.
+fn machine(preset: QuirkPreset) -> Setup {
+    let mut chip8 = Chip8::new(Platform::Chip8, preset.quirks());
+    chip8.load(&[]);
+    chip8.seed(0);
+    Setup { chip8, preset }
+}
+
+struct Setup {
+    chip8: Chip8,
+    preset: QuirkPreset,
+}
+
+impl Setup {
+    fn v(mut self, x: usize, value: u8) -> Setup {
+        self.chip8.registers[x] = value;
+        self
+    }
+    fn i(mut self, address: u16) -> Setup {
+        self.chip8.i_register = address;
+        self
+    }
+    fn memory(mut self, address: u16, bytes: &[u8]) -> Setup {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.chip8.write(address + i as u16, byte);
+        }
+        self
+    }
+    fn pixel(mut self, x: usize, y: usize) -> Setup {
+        self.chip8.screen[y][x] = 1;
+        self
+    }
+    /// Return addresses, the last one returned to first.
+    fn stack(mut self, addresses: &[u16]) -> Setup {
+        for &address in addresses {
+            self.chip8.stack.push(address).unwrap();
+        }
+        self
+    }
+    fn delay(self, value: u8) -> Setup {
+        self.chip8.timers.lock().unwrap().delay_timer = value;
+        self
+    }
+    fn key(mut self, key: u8) -> Setup {
+        self.chip8.key = Some(key);
+        self
+    }
+    /// As if Fx0A had already run once without a key.
+    fn waiting_for_key(mut self) -> Setup {
+        self.chip8.waiting_for_key = true;
+        self
+    }
+
+    fn run(mut self, opcode: u16) -> Outcome {
+        let [high, low] = opcode.to_be_bytes();
+        let pc = self.chip8.program_counter;
+        self.chip8.write(pc, high);
+        self.chip8.write(pc + 1, low);
+        let before = Snapshot::of(&self.chip8);
+        step(&mut self.chip8);
+        Outcome {
+            preset: self.preset,
+            opcode,
+            changes: before.changes(&Snapshot::of(&self.chip8)),
+        }
+    }
+}
+
+struct Outcome {
+    preset: QuirkPreset,
+    opcode: u16,
+    changes: Vec<Change>,
+}
+
+impl Outcome {
+    /// In any order.
+    fn changed(mut self, expected: &[Change]) {
+        let mut expected = expected.to_vec();
+        expected.sort();
+        self.changes.sort();
+        assert_eq!(
+            self.changes, expected,
+            "{:04X} with {} quirks",
+            self.opcode, self.preset
+        );
+    }
+}
+
+#[test]
+fn sys_is_ignored_by_default() {
+    for preset in PRESETS {
+        machine(preset).run(0x0123).changed(&[]);
+    }
+}
+
+#[test]
+fn sys_can_stop_the_program() {
+    for preset in PRESETS {
+        let mut setup = machine(preset);
+        setup.chip8.set_sys(Sys::Error);
+        setup
+            .run(0x0123)
+            .changed(&[Pc(0x200), Stopped(crate::Fault::Sys(0x123))]);
+    }
+}
+
+#[test]
+fn sys_runs_the_handler_for_its_address() {
+    for preset in PRESETS {
+        let mut setup = machine(preset);
+        setup.chip8.set_sys(Sys::Error);
+        setup.chip8.on_sys(0x123, |chip8| chip8.registers[0] += 1);
+        setup.chip8.on_sys(0x456, |chip8| chip8.registers[1] += 1);
+        setup.run(0x0123).changed(&[V(0, 1)]);
+    }
+}
+
+#[test]
+fn cls() {
+    for preset in PRESETS {
+        machine(preset)
+            .pixel(0, 0)
+            .pixel(63, 31)
+            .run(0x00E0)
+            .changed(&[Pixel(0, 0, false), Pixel(63, 31, false)]);
+    }
+}
+
+#[test]
+fn ret() {
+    for preset in PRESETS {
+        machine(preset)
+            .stack(&[0x300, 0x400])
+            .run(0x00EE)
+            .changed(&[Pc(0x402), Sp(1)]);
+    }
+}
+
+#[test]
+fn jp() {
+    for preset in PRESETS {
+        machine(preset).run(0x1234).changed(&[Pc(0x234)]);
+    }
+}
+
+#[test]
+fn call() {
+    for preset in PRESETS {
+        machine(preset)
+            .run(0x2345)
+            .changed(&[Pc(0x345), Sp(1), Stack(0, 0x200)]);
+        machine(preset)
+            .stack(&[0x300])
+            .run(0x2345)
+            .changed(&[Pc(0x345), Sp(2), Stack(1, 0x200)]);
+    }
+}
+
+#[test]
+fn overflowing_or_underflowing_the_stack_stops_the_program() {
+    for preset in PRESETS {
+        let full = vec![0x300; preset.quirks().stack_depth];
+        machine(preset)
+            .stack(&full)
+            .run(0x2345)
+            .changed(&[Pc(0x200), Stopped(StackError::Overflow.into())]);
+        machine(preset)
+            .run(0x00EE)
+            .changed(&[Pc(0x200), Stopped(StackError::Underflow.into())]);
+    }
+}
+
+#[test]
+fn nothing_runs_after_a_fault() {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    // RET; ADD V1, 1
+    chip8.load(&[0x00, 0xEE, 0x71, 0x01]);
+    assert_eq!(run_instructions(&mut chip8, 10), 1);
+    assert_eq!(run_instructions(&mut chip8, 10), 0);
+    assert_eq!(chip8.registers[1], 0);
+    assert_eq!(chip8.fault(), Some(StackError::Underflow.into()));
+}
+
+#[test]
+fn se_and_sne() {
+    for preset in PRESETS {
+        machine(preset).v(1, 0x42).run(0x3142).changed(&[Pc(0x204)]);
+        machine(preset).v(1, 0x42).run(0x3143).changed(&[]);
+        machine(preset).v(1, 0x42).run(0x4142).changed(&[]);
+        machine(preset).v(1, 0x42).run(0x4143).changed(&[Pc(0x204)]);
+        machine(preset)
+            .v(1, 7)
+            .v(2, 7)
+            .run(0x5120)
+            .changed(&[Pc(0x204)]);
+        machine(preset).v(1, 7).v(2, 8).run(0x5120).changed(&[]);
+        machine(preset).v(1, 7).v(2, 7).run(0x9120).changed(&[]);
+        machine(preset)
+            .v(1, 7)
+            .v(2, 8)
+            .run(0x9120)
+            .changed(&[Pc(0x204)]);
+    }
+}
+
+#[test]
+fn ld_and_add_byte() {
+    for preset in PRESETS {
+        machine(preset).run(0x6A42).changed(&[V(0xA, 0x42)]);
+        machine(preset)
+            .v(0xA, 1)
+            .run(0x7A42)
+            .changed(&[V(0xA, 0x43)]);
+        // no carry flag
+        machine(preset)
+            .v(0xA, 0xFF)
+            .run(0x7A02)
+            .changed(&[V(0xA, 0x01)]);
+    }
+}
+
+#[test]
+fn ld_vx_vy() {
+    for preset in PRESETS {
+        machine(preset).v(2, 9).run(0x8120).changed(&[V(1, 9)]);
+    }
+}
+
+#[test]
+fn logic_resets_vf_on_vip() {
+    for preset in PRESETS {
+        let reset: &[Change] = if preset.quirks().vf_reset {
+            &[V(0xF, 0)]
+        } else {
+            &[]
+        };
+        for (opcode, result) in [(0x8121, 0b1110), (0x8122, 0b1000), (0x8123, 0b0110)] {
+            machine(preset)
+                .v(1, 0b1100)
+                .v(2, 0b1010)
+                .v(0xF, 1)
+                .run(opcode)
+                .changed(&[&[V(1, result)], reset].concat());
+        }
+    }
+}
+
+#[test]
+fn add_with_carry() {
+    for preset in PRESETS {
+        machine(preset)
+            .v(1, 0x10)
+            .v(2, 0x20)
+            .v(0xF, 1)
+            .run(0x8124)
+            .changed(&[V(1, 0x30), V(0xF, 0)]);
+        machine(preset)
+            .v(1, 0xF0)
+            .v(2, 0x20)
+            .run(0x8124)
+            .changed(&[V(1, 0x10), V(0xF, 1)]);
+    }
+}
+
+#[test]
+fn sub_and_subn() {
+    for preset in PRESETS {
+        machine(preset)
+            .v(1, 5)
+            .v(2, 3)
+            .run(0x8125)
+            .changed(&[V(1, 2), V(0xF, 1)]);
+        machine(preset)
+            .v(1, 3)
+            .v(2, 5)
+            .v(0xF, 1)
+            .run(0x8125)
+            .changed(&[V(1, 0xFE), V(0xF, 0)]);
+        // equal operands don't borrow
+        machine(preset)
+            .v(1, 5)
+            .v(2, 5)
+            .run(0x8125)
+            .changed(&[V(1, 0), V(0xF, 1)]);
+        machine(preset)
+            .v(1, 3)
+            .v(2, 5)
+            .run(0x8127)
+            .changed(&[V(1, 2), V(0xF, 1)]);
+        machine(preset)
+            .v(1, 5)
+            .v(2, 3)
+            .v(0xF, 1)
+            .run(0x8127)
+            .changed(&[V(1, 0xFE), V(0xF, 0)]);
+    }
+}
+
+#[test]
+fn shifts_take_vy_on_vip_and_xo_chip() {
+    for preset in PRESETS {
+        let shift_vx = preset.quirks().shift_vx;
+        // V1 = 0b1000_0011, V2 = 0b0100_0010
+        let (right, right_flag) = if shift_vx { (0x41, 1) } else { (0x21, 0) };
+        machine(preset)
+            .v(1, 0x83)
+            .v(2, 0x42)
+            .v(0xF, 0x55)
+            .run(0x8126)
+            .changed(&[V(1, right), V(0xF, right_flag)]);
+        let (left, left_flag) = if shift_vx { (0x06, 1) } else { (0x84, 0) };
+        machine(preset)
+            .v(1, 0x83)
+            .v(2, 0x42)
+            .v(0xF, 0x55)
+            .run(0x812E)
+            .changed(&[V(1, left), V(0xF, left_flag)]);
+    }
+}
+
+/// VF can be an operand of the instructions that set it. The flag is written
+/// last, so it always wins over the result.
+#[test]
+fn flags_win_over_results_in_vf() {
+    for preset in PRESETS {
+        machine(preset)
+            .v(0xF, 0x80)
+            .run(0x8FF4)
+            .changed(&[V(0xF, 1)]);
+        machine(preset)
+            .v(0xF, 0x01)
+            .run(0x8FF4)
+            .changed(&[V(0xF, 0)]);
+        machine(preset)
+            .v(0, 0xFF)
+            .v(0xF, 1)
+            .run(0x80F4)
+            .changed(&[V(0, 0)]);
+        machine(preset)
+            .v(0, 3)
+            .v(0xF, 5)
+            .run(0x8F05)
+            .changed(&[V(0xF, 1)]);
+        machine(preset)
+            .v(0, 5)
+            .v(0xF, 3)
+            .run(0x8F05)
+            .changed(&[V(0xF, 0)]);
+        machine(preset)
+            .v(0, 3)
+            .v(0xF, 5)
+            .run(0x8F07)
+            .changed(&[V(0xF, 0)]);
+        machine(preset)
+            .v(0xF, 0x81)
+            .run(0x8FF6)
+            .changed(&[V(0xF, 1)]);
+        machine(preset)
+            .v(0xF, 0x40)
+            .run(0x8FFE)
+            .changed(&[V(0xF, 0)]);
+        // which of V0 and VF gets shifted depends on the quirk, the flag still wins
+        let flag = if preset.quirks().shift_vx { 1 } else { 0 };
+        machine(preset)
+            .v(0, 0x02)
+            .v(0xF, 0x03)
+            .run(0x8F06)
+            .changed(&[V(0xF, flag)]);
+    }
+}
+
+#[test]
+fn ld_i() {
+    for preset in PRESETS {
+        machine(preset).run(0xA123).changed(&[I(0x123)]);
+    }
+}
+
+#[test]
+fn jp_v0_jumps_with_vx_on_schip() {
+    for preset in PRESETS {
+        let target = if preset.quirks().jump_vx {
+            0x365
+        } else {
+            0x355
+        };
+        machine(preset)
+            .v(0, 0x10)
+            .v(3, 0x20)
+            .run(0xB345)
+            .changed(&[Pc(target)]);
+    }
+}
+
+#[test]
+fn rnd_masks_the_random_byte() {
+    let byte = Xorshift::seeded(0).byte();
+    for preset in PRESETS {
+        machine(preset)
+            .v(0, 0xFF)
+            .run(0xC00F)
+            .changed(&[V(0, byte & 0x0F)]);
+    }
+}
+
+/// Hands out the same bytes in order, over and over.
+struct Scripted {
+    bytes: Vec<u8>,
+    next: usize,
+}
+
+impl Random for Scripted {
+    fn byte(&mut self) -> u8 {
+        let byte = self.bytes[self.next % self.bytes.len()];
+        self.next += 1;
+        byte
+    }
+    fn state(&self) -> Vec<u8> {
+        vec![self.next as u8]
+    }
+    fn restore(&mut self, state: &[u8]) -> Result<(), String> {
+        self.next = state[0] as usize;
+        Ok(())
+    }
+}
+
+#[test]
+fn rnd_takes_its_bytes_from_the_generator_it_is_given() {
+    for preset in PRESETS {
+        let mut setup = machine(preset);
+        setup.chip8.set_rng(Box::new(Scripted {
+            bytes: vec![0xA5, 0x3C],
+            next: 0,
+        }));
+        setup.run(0xC1F0).changed(&[V(1, 0xA0)]);
+    }
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    chip8.set_rng(Box::new(Scripted {
+        bytes: vec![0xA5, 0x3C],
+        next: 0,
+    }));
+    // RND V1, 0xF0; RND V2, 0x0F; RND V3, 0xFF
+    chip8.load(&[0xC1, 0xF0, 0xC2, 0x0F, 0xC3, 0xFF]);
+    run_instructions(&mut chip8, 3);
+    assert_eq!(chip8.registers[1..4], [0xA0, 0x0C, 0xA5]);
+}
+
+#[test]
+fn drw() {
+    for preset in PRESETS {
+        // the top two rows of the font's 0
+        machine(preset).v(0, 2).v(1, 3).run(0xD012).changed(&[
+            Pixel(2, 3, true),
+            Pixel(3, 3, true),
+            Pixel(4, 3, true),
+            Pixel(5, 3, true),
+            Pixel(2, 4, true),
+            Pixel(5, 4, true),
+        ]);
+        machine(preset)
+            .pixel(2, 3)
+            .v(0, 2)
+            .v(1, 3)
+            .i(0x300)
+            .memory(0x300, &[0xC0])
+            .run(0xD011)
+            .changed(&[Pixel(2, 3, false), Pixel(3, 3, true), V(0xF, 1)]);
+        // the start wraps even with clipping
+        machine(preset)
+            .v(0, 66)
+            .v(1, 33)
+            .i(0x300)
+            .memory(0x300, &[0x80])
+            .run(0xD011)
+            .changed(&[Pixel(2, 1, true)]);
+    }
+}
+
+#[test]
+fn drw_clips_at_the_edges_except_on_xo_chip() {
+    for preset in PRESETS {
+        let mut expected = vec![Pixel(63, 31, true)];
+        if !preset.quirks().clipping {
+            expected.extend([Pixel(0, 31, true), Pixel(63, 0, true), Pixel(0, 0, true)]);
+        }
+        machine(preset)
+            .v(0, 63)
+            .v(1, 31)
+            .i(0x300)
+            .memory(0x300, &[0xC0, 0xC0])
+            .run(0xD012)
+            .changed(&expected);
+    }
+}
+
+#[test]
+fn drw_reads_vf_coordinates_before_setting_the_flag() {
+    for preset in PRESETS {
+        machine(preset)
+            .v(0xF, 10)
+            .v(1, 3)
+            .i(0x300)
+            .memory(0x300, &[0x80])
+            .run(0xDF11)
+            .changed(&[Pixel(10, 3, true), V(0xF, 0)]);
+    }
+}
+
+#[test]
+fn drw_ends_the_frame_with_display_wait() {
+    for preset in PRESETS {
+        for engine in [Engine::Interpreter, Engine::Blocks] {
+            let mut chip8 = Chip8::new(Platform::Chip8, preset.quirks());
+            chip8.set_engine(engine);
+            // DRW V0, V0, 1; ADD V1, 1; JP 0x200
+            chip8.load(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00]);
+            let executed = run_instructions(&mut chip8, 10);
+            if preset.quirks().display_wait {
+                assert_eq!(executed, 1, "{} on {}", engine, preset);
+                assert_eq!(run_instructions(&mut chip8, 10), 0);
+                tick_timers(&mut chip8);
+                assert_eq!(run_instructions(&mut chip8, 10), 3);
+                assert_eq!(chip8.registers[1], 1);
+            } else {
+                assert_eq!(executed, 10, "{} on {}", engine, preset);
+            }
+        }
+    }
+}
+
+#[test]
+fn skp_and_sknp() {
+    for preset in PRESETS {
+        machine(preset)
+            .v(1, 5)
+            .key(5)
+            .run(0xE19E)
+            .changed(&[Pc(0x204), Key(None)]);
+        machine(preset).v(1, 5).key(6).run(0xE19E).changed(&[]);
+        machine(preset).v(1, 5).run(0xE19E).changed(&[]);
+        machine(preset)
+            .v(1, 5)
+            .key(5)
+            .run(0xE1A1)
+            .changed(&[Key(None)]);
+        machine(preset)
+            .v(1, 5)
+            .key(6)
+            .run(0xE1A1)
+            .changed(&[Pc(0x204)]);
+        machine(preset).v(1, 5).run(0xE1A1).changed(&[Pc(0x204)]);
+    }
+}
+
+#[test]
+fn ld_vx_k_waits_for_a_new_key() {
+    for preset in PRESETS {
+        machine(preset)
+            .run(0xF30A)
+            .changed(&[Pc(0x200), Waiting(true)]);
+        // held before Fx0A started
+        machine(preset)
+            .key(5)
+            .run(0xF30A)
+            .changed(&[Pc(0x200), Waiting(true), Key(None)]);
+        machine(preset)
+            .waiting_for_key()
+            .key(5)
+            .run(0xF30A)
+            .changed(&[V(3, 5), Waiting(false), Key(None)]);
+    }
+}
+
+#[test]
+fn timers() {
+    for preset in PRESETS {
+        machine(preset).delay(30).run(0xF307).changed(&[V(3, 30)]);
+        machine(preset).v(3, 30).run(0xF315).changed(&[Delay(30)]);
+        machine(preset).v(3, 30).run(0xF318).changed(&[Sound(30)]);
+    }
+}
+
+#[test]
+fn audio_and_pitch() {
+    let pattern = [
+        0xF0, 0x0F, 0xAA, 0x00, 0xFF, 0x81, 0x3C, 0x55, 0xF0, 0x0F, 0xAA, 0x00, 0xFF, 0x81, 0x3C,
+        0x55,
+    ];
+    for preset in PRESETS {
+        machine(preset)
+            .i(0x300)
+            .memory(0x300, &pattern)
+            .run(0xF002)
+            .changed(&[Pattern(pattern)]);
+        machine(preset).v(3, 100).run(0xF33A).changed(&[Pitch(100)]);
+    }
+}
+
+#[test]
+fn add_i() {
+    for preset in PRESETS {
+        machine(preset)
+            .i(0x300)
+            .v(3, 0x20)
+            .run(0xF31E)
+            .changed(&[I(0x320)]);
+    }
+}
+
+#[test]
+fn ld_f_points_at_the_font() {
+    for preset in PRESETS {
+        machine(preset).v(3, 0xA).run(0xF329).changed(&[I(50)]);
+    }
+}
+
+#[test]
+fn ld_b() {
+    for preset in PRESETS {
+        machine(preset).i(0x300).v(3, 234).run(0xF333).changed(&[
+            Memory(0x300, 2),
+            Memory(0x301, 3),
+            Memory(0x302, 4),
+        ]);
+        machine(preset)
+            .i(0x300)
+            .v(3, 7)
+            .run(0xF333)
+            .changed(&[Memory(0x302, 7)]);
+    }
+}
+
+#[test]
+fn store_and_load_registers_move_i_except_on_schip() {
+    for preset in PRESETS {
+        let moved: &[Change] = if preset.quirks().memory_increment {
+            &[I(0x303)]
+        } else {
+            &[]
+        };
+        machine(preset)
+            .i(0x300)
+            .v(0, 1)
+            .v(1, 2)
+            .v(2, 3)
+            .v(3, 4)
+            .run(0xF255)
+            .changed(
+                &[
+                    &[Memory(0x300, 1), Memory(0x301, 2), Memory(0x302, 3)],
+                    moved,
+                ]
+                .concat(),
+            );
+        machine(preset)
+            .i(0x300)
+            .memory(0x300, &[1, 2, 3, 4])
+            .run(0xF265)
+            .changed(&[&[V(0, 1), V(1, 2), V(2, 3)], moved].concat());
+    }
+}
+
+#[test]
+fn opcodes_decode_like_their_hex() {
+    for opcode in 0..=u16::MAX {
+        assert_eq!(
+            decode((opcode >> 8) as u8, opcode as u8),
+            Instruction::from_str(&format!("{:04X}", opcode)).ok(),
+            "{:04X}",
+            opcode
+        );
+    }
+}
+
+#[test]
+fn programs_can_change_their_own_code() {
+    for engine in [Engine::Interpreter, Engine::Blocks] {
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        chip8.set_engine(engine);
+        chip8.load(&[
+            0x61, 0x00, // LD V1, 0, turns into LD V1, 5
+            0x60, 0x05, // LD V0, 5
+            0xA2, 0x01, // LD I, 0x201
+            0xF0, 0x55, // LD [I], V0
+            0x12, 0x00, // JP 0x200
+        ]);
+        run_instructions(&mut chip8, 6);
+        assert_eq!(chip8.registers[1], 5, "{}", engine);
+    }
+}
+
+#[test]
+fn vip_timing_runs_a_frame_of_cycles() {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    // ADD V1, 1; JP 0x200, 102 cycles together
+    chip8.load(&[0x71, 0x01, 0x12, 0x00]);
+    assert_eq!(run_cycles(&mut chip8), 36);
+    assert_eq!(run_cycles(&mut chip8), 36);
+    assert_eq!(chip8.registers[1], 36);
+}
+
+#[test]
+fn vip_timing_carries_long_instructions_into_the_next_frame() {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    // CLS; JP 0x200, where CLS alone takes more than a frame
+    chip8.load(&[0x00, 0xE0, 0x12, 0x00]);
+    assert_eq!(run_cycles(&mut chip8), 1);
+    assert_eq!(run_cycles(&mut chip8), 2);
+    assert_eq!(run_cycles(&mut chip8), 0);
+}
+
+#[test]
+fn vip_timing_of_drw_depends_on_height_and_alignment() {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    let drw = |n| Some(Instruction::DRW(0, 1, n));
+    let aligned = timing::cycles(&chip8, drw(5));
+    assert!(timing::cycles(&chip8, drw(1)) < aligned);
+    chip8.registers[0] = 3;
+    assert!(timing::cycles(&chip8, drw(5)) > aligned);
+}
+
+/// Ignores writes to the font.
+struct ReadOnlyFont(Ram);
+
+impl Bus for ReadOnlyFont {
+    fn read(&mut self, address: u16) -> u8 {
+        self.0.read(address)
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        if address as usize >= FONT.len() {
+            self.0.write(address, value);
+        }
+    }
+    fn memory(&self) -> &[u8] {
+        self.0.memory()
+    }
+    fn memory_mut(&mut self) -> &mut [u8] {
+        self.0.memory_mut()
+    }
+}
+
+struct CountingReads {
+    ram: Ram,
+    reads: Rc<Cell<usize>>,
+}
+
+impl Bus for CountingReads {
+    fn read(&mut self, address: u16) -> u8 {
+        self.reads.set(self.reads.get() + 1);
+        self.ram.read(address)
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        self.ram.write(address, value);
+    }
+    fn memory(&self) -> &[u8] {
+        self.ram.memory()
+    }
+    fn memory_mut(&mut self) -> &mut [u8] {
+        self.ram.memory_mut()
+    }
+}
+
+#[test]
+fn buses_can_protect_memory() {
+    let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+    chip8.set_bus(Box::new(ReadOnlyFont(Ram::new(0x1000))));
+    chip8.load(&[
+        0x60, 0x42, // LD V0, 0x42
+        0xA0, 0x00, // LD I, 0
+        0xF0, 0x55, // LD [I], V0
+        0xA3, 0x00, // LD I, 0x300
+        0xF0, 0x55, // LD [I], V0
+    ]);
+    run_instructions(&mut chip8, 5);
+    assert_eq!(chip8.bus().memory()[0], FONT[0]);
+    assert_eq!(chip8.bus().memory()[0x300], 0x42);
+}
+
+#[test]
+fn buses_see_first_fetches_and_every_data_read() {
+    for engine in [Engine::Interpreter, Engine::Blocks] {
+        let reads = Rc::new(Cell::new(0));
+        let mut chip8 = Chip8::new(Platform::Chip8, QuirkPreset::Vip.quirks());
+        chip8.set_engine(engine);
+        chip8.set_bus(Box::new(CountingReads {
+            ram: Ram::new(0x1000),
+            reads: Rc::clone(&reads),
+        }));
+        chip8.load(&[
+            0xA0, 0x00, // LD I, 0
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x12, 0x04, // JP 0x204
+        ]);
+        // the sprite ends the first frame
+        for _ in 0..2 {
+            run_instructions(&mut chip8, 10);
+            tick_timers(&mut chip8);
+        }
+        // two bytes for each instruction and the five of the sprite
+        assert_eq!(reads.get(), 3 * 2 + 5, "{}", engine);
+        // the jump comes from the decoded cache from now on
+        run_instructions(&mut chip8, 10);
+        assert_eq!(reads.get(), 3 * 2 + 5, "{}", engine);
+    }
+}
@@ -0,0 +1,119 @@
+//! How much the COSMAC VIP's interpreter took for each instruction, for
+//! running programs at the speed they had there instead of a fixed number of
+//! instructions per frame.
+//!
+//! Times are in 1802 machine cycles, eight clocks each at 1.7609MHz. They're
+//! close to what the interpreter's routines take rather than exact to the
+//! cycle, and the display interrupt is a fixed share of every frame.
+
+use crate::{Chip8, Instruction};
+use std::fmt;
+use std::str::FromStr;
+
+/// Machine cycles between two 60Hz interrupts.
+pub const CYCLES_PER_FRAME: u32 = 3668;
+/// What the interrupt takes out of each frame: the 1861 reads 128 lines of
+/// display memory through DMA while the interrupt routine keeps pointing it
+/// at the right row, then the routine counts the timers down.
+pub const INTERRUPT_CYCLES: u32 = 1832;
+/// What's left of a frame for running instructions.
+pub const FRAME_BUDGET: u32 = CYCLES_PER_FRAME - INTERRUPT_CYCLES;
+
+/// Fetching, decoding and dispatching, before the instruction's own routine.
+const FETCH: u32 = 40;
+/// Skipping over the next instruction once the condition held.
+const SKIP: u32 = 4;
+
+/// What a frame is made of.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Timing {
+    /// The scheduler's instructions per frame, whatever they are.
+    #[default]
+    Ipf,
+    /// As many instructions as fit in a VIP frame's machine cycles.
+    Vip,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Timing::Ipf => write!(f, "ipf"),
+            Timing::Vip => write!(f, "vip"),
+        }
+    }
+}
+
+impl FromStr for Timing {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ipf" => Ok(Timing::Ipf),
+            "vip" => Ok(Timing::Vip),
+            _ => Err(format!("unknown timing '{}', expected ipf or vip", s)),
+        }
+    }
+}
+
+/// The machine cycles `instruction` takes when run on `chip8` as it is now,
+/// before the instruction changes it. Opcodes the VIP didn't have, and ones
+/// that don't decode, only cost the fetch.
+pub(crate) fn cycles(chip8: &Chip8, instruction: Option<Instruction>) -> u32 {
+    let v = |register: u8| chip8.registers[register as usize];
+    let skip = |condition: bool| if condition { SKIP } else { 0 };
+    let Some(instruction) = instruction else {
+        return FETCH;
+    };
+    FETCH
+        + match instruction {
+            // clears all 256 bytes of display memory, longer than a frame
+            Instruction::CLS => 3078,
+            Instruction::RET => 10,
+            Instruction::JPaddr(_) => 12,
+            Instruction::CallAddr(_) => 26,
+            Instruction::SEVx(x, kk) => 10 + skip(v(x) == kk),
+            Instruction::SNEVx(x, kk) => 10 + skip(v(x) != kk),
+            Instruction::SEVxVy(x, y) => 14 + skip(v(x) == v(y)),
+            Instruction::SNE(x, y) => 14 + skip(v(x) != v(y)),
+            Instruction::SKP(x) => 14 + skip(chip8.key == Some(v(x))),
+            Instruction::SKNP(x) => 14 + skip(chip8.key != Some(v(x))),
+            Instruction::LDVx(..) => 6,
+            Instruction::ADDVx(..) => 10,
+            // the 8xy_ routines are built in RAM and all take the same time
+            Instruction::LDVxVy(..)
+            | Instruction::ORVxVy(..)
+            | Instruction::ANDVxVy(..)
+            | Instruction::XORVxVy(..)
+            | Instruction::ADDVxVy(..)
+            | Instruction::SUBVxVy(..)
+            | Instruction::SHRVx(..)
+            | Instruction::SUBN(..)
+            | Instruction::SHL(..) => 20,
+            Instruction::LDI(_) => 12,
+            // carrying into the high byte of the address takes longer
+            Instruction::JPV0ADDR(address) => {
+                if (address & 0xFF) + v(0) as u16 > 0xFF {
+                    24
+                } else {
+                    22
+                }
+            }
+            Instruction::RNDVx(..) => 36,
+            Instruction::DRW(x, _, n) => {
+                // sprites off a byte boundary get shifted across two bytes
+                let row = if v(x) % 8 == 0 { 46 } else { 68 };
+                68 + row * n as u32
+            }
+            Instruction::LDVxDT(_) | Instruction::LDDTVx(_) | Instruction::LDSTVx(_) => 10,
+            // each check while waiting for a key
+            Instruction::LDVxK(_) => 10,
+            Instruction::ADDIVx(_) => 16,
+            Instruction::LDFVx(_) => 16,
+            // divides by counting subtractions, so bigger digits take longer
+            Instruction::LDBVx(x) => {
+                let value = v(x) as u32;
+                80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            Instruction::LDIVx(x) | Instruction::LDVxI(x) => 14 + 14 * (x as u32 + 1),
+            Instruction::SysAddr(_) | Instruction::AUDIO | Instruction::PITCHVx(_) => 0,
+        }
+}
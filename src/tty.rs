@@ -0,0 +1,121 @@
+//! The terminal frontend. Puts the terminal in raw mode on the alternate
+//! screen and makes sure it comes back out, whether the emulator returns,
+//! exits with an error or panics.
+
+use crate::hud::Hud;
+use chip8::frontend::{Display, Input, InputEvent};
+use chip8::hotkeys::Hotkeys;
+use chip8::keymap::Keymap;
+use chip8::render::{Renderer, Screen};
+use chip8::scheduler::Scheduler;
+use crossterm::{
+    cursor,
+    event::{poll, read, Event, KeyCode},
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use std::io::{self, stdout, Stdout, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Whether the terminal needs restoring.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Raw mode and the alternate screen for as long as it lives.
+pub struct TerminalGuard(());
+
+impl TerminalGuard {
+    pub fn enter() -> io::Result<TerminalGuard> {
+        terminal::enable_raw_mode()?;
+        ACTIVE.store(true, Ordering::SeqCst);
+        stdout()
+            .execute(EnterAlternateScreen)?
+            .execute(cursor::Hide)?;
+        Ok(TerminalGuard(()))
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+/// Leaves raw mode and the alternate screen if a guard put the terminal
+/// there. Errors are ignored since there is nothing left to tell them on.
+pub fn restore() {
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        let mut stdout = stdout();
+        let _ = stdout.execute(cursor::Show);
+        let _ = stdout.execute(LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Restores the terminal before the panic message is printed, so it shows up
+/// on the normal screen and in cooked mode.
+pub fn install_panic_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default(info);
+    }));
+}
+
+/// The screen with the status line under it.
+pub struct TerminalDisplay {
+    pub renderer: Box<dyn Renderer>,
+    pub hud: Hud,
+    stdout: Stdout,
+}
+
+impl TerminalDisplay {
+    pub fn new(renderer: Box<dyn Renderer>, hud: Hud) -> TerminalDisplay {
+        TerminalDisplay {
+            renderer,
+            hud,
+            stdout: stdout(),
+        }
+    }
+}
+
+impl Display for TerminalDisplay {
+    fn draw(&mut self, screen: &Screen, scheduler: &Scheduler, quirks: &str) -> io::Result<()> {
+        self.renderer.draw(screen)?;
+        self.hud
+            .draw(&mut self.stdout, self.renderer.rows(), scheduler, quirks)?;
+        self.stdout.flush()
+    }
+    fn message(&mut self, text: String) {
+        self.hud.message(text);
+    }
+}
+
+/// Key presses from the terminal, through the keymap and the hotkeys.
+pub struct TerminalInput<'a> {
+    pub keymap: Keymap,
+    pub hotkeys: &'a Hotkeys,
+}
+
+impl Input for TerminalInput<'_> {
+    fn poll(&mut self) -> io::Result<Vec<InputEvent>> {
+        let mut events = Vec::new();
+        while poll(Duration::from_millis(0))? {
+            let Event::Key(event) = read()? else {
+                continue;
+            };
+            // keys the keymap uses belong to the chip8, even if they are also hotkeys
+            let key = match event.code {
+                KeyCode::Char(c) => self.keymap.key(c),
+                _ => None,
+            };
+            if let Some(key) = key {
+                events.push(InputEvent::Key(key));
+            } else if let Some(action) = self.hotkeys.action(event.code) {
+                events.push(InputEvent::Action(action));
+            }
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,54 @@
+//! Runs roms on the interpreter and the block engine side by side and checks
+//! they agree on the whole machine state after every frame.
+
+use chip8::blocks::Engine;
+use chip8::rom::{Rom, Settings};
+use chip8::{run_instructions, state, tick_timers, Chip8};
+use std::path::Path;
+
+const ROMS: [&str; 9] = [
+    "IBM_Logo.ch8",
+    "chip8-test-rom.ch8",
+    "3-corax+.ch8",
+    "4-flags.ch8",
+    "5-quirks.ch8",
+    "BC_test.ch8",
+    "test_opcode.ch8",
+    "Airplane.ch8",
+    "tetris.rom",
+];
+const FRAMES: usize = 300;
+
+fn machine(rom: &Rom, engine: Engine) -> Chip8 {
+    let mut chip8 = Chip8::new(rom.platform, rom.settings(Settings::default()).quirks);
+    chip8.seed(0);
+    chip8.load(&rom.bytes);
+    chip8.set_engine(engine);
+    chip8
+}
+
+#[test]
+fn blocks_run_like_the_interpreter() {
+    for file in ROMS {
+        let rom = Rom::load(Path::new(env!("CARGO_MANIFEST_DIR")).join(file), None).unwrap();
+        let rom_ipf = rom.settings(Settings::default()).ipf;
+        // 1 checks every instruction, 7 cuts blocks off in odd places
+        for ipf in [1, 7, rom_ipf] {
+            let mut interpreter = machine(&rom, Engine::Interpreter);
+            let mut blocks = machine(&rom, Engine::Blocks);
+            for frame in 0..FRAMES {
+                for chip8 in [&mut interpreter, &mut blocks] {
+                    run_instructions(chip8, ipf);
+                    tick_timers(chip8);
+                }
+                assert!(
+                    state::save(&interpreter) == state::save(&blocks),
+                    "{} at {} ipf differs after frame {}",
+                    file,
+                    ipf,
+                    frame
+                );
+            }
+        }
+    }
+}
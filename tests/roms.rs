@@ -0,0 +1,125 @@
+//! Runs the bundled test roms headless and compares the screen they end on
+//! with the one in `tests/screens`.
+
+use chip8::audio::{Beeper, NullSink, Tone};
+use chip8::frontend::{FakeClock, HeadlessDisplay, InputEvent, ScriptedInput};
+use chip8::hotkeys::Action;
+use chip8::render;
+use chip8::rom::{Rom, Settings};
+use chip8::scheduler::Scheduler;
+use chip8::{program, Chip8};
+use std::path::Path;
+
+/// Where the Timendus roms look for a menu choice made before they started,
+/// so they skip their menu.
+const MENU_CHOICE: u16 = 0x1FF;
+
+/// Runs `file` with the settings from the rom database for `frames` frames,
+/// pressing each of `keys` on its frame. `setup` gets the machine after the
+/// rom is loaded.
+fn screen(file: &str, frames: u64, keys: &[(u64, u8)], setup: impl FnOnce(&mut Chip8)) -> String {
+    let rom = Rom::load(Path::new(env!("CARGO_MANIFEST_DIR")).join(file), None).unwrap();
+    assert!(rom.entry.is_some(), "test roms are in the database");
+    let settings = rom.settings(Settings::default());
+    let mut chip8 = Chip8::new(rom.platform, settings.quirks);
+    chip8.seed(0);
+    chip8.load(&rom.bytes);
+    setup(&mut chip8);
+    let mut events: Vec<(u64, InputEvent)> = keys
+        .iter()
+        .map(|&(frame, key)| (frame, InputEvent::Key(key)))
+        .collect();
+    events.push((frames, InputEvent::Action(Action::Quit)));
+    program(
+        &mut chip8,
+        &mut HeadlessDisplay,
+        &mut ScriptedInput::new(events),
+        &mut Beeper::new(Tone::default(), Box::new(NullSink)),
+        Scheduler::new(settings.ipf, Box::new(FakeClock::default())),
+        None,
+        Path::new("test.state"),
+    )
+    .unwrap();
+    render::text(&chip8.screen)
+}
+
+fn check(file: &str, frames: u64, setup: impl FnOnce(&mut Chip8)) {
+    check_with_keys(file, frames, &[], setup)
+}
+
+fn check_with_keys(file: &str, frames: u64, keys: &[(u64, u8)], setup: impl FnOnce(&mut Chip8)) {
+    let actual = screen(file, frames, keys, setup);
+    let expected_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/screens")
+        .join(Path::new(file).with_extension("txt"));
+    let expected = std::fs::read_to_string(&expected_path).unwrap();
+    assert!(
+        actual == expected,
+        "{} ended on\n{}\ninstead of\n{}",
+        file,
+        actual,
+        expected
+    );
+}
+
+#[test]
+fn ibm_logo() {
+    check("IBM_Logo.ch8", 60, |_| {});
+}
+
+// chip8-logo.ch8 isn't here: the bundled copy went through a text encoding at
+// some point and every byte above 0x7F is now U+FFFD.
+
+#[test]
+fn tronix_test() {
+    check("chip8-test-rom.ch8", 60, |_| {});
+}
+
+#[test]
+fn corax_plus() {
+    check("3-corax+.ch8", 200, |_| {});
+}
+
+#[test]
+fn flags() {
+    check("4-flags.ch8", 200, |_| {});
+}
+
+#[test]
+fn quirks_for_chip8() {
+    check("5-quirks.ch8", 600, |chip8| {
+        chip8.bus_mut().write(MENU_CHOICE, 1)
+    });
+}
+
+#[test]
+fn keypad_fx0a() {
+    // the third test waits for a key with Fx0A and says all good once one
+    // comes in
+    check_with_keys("6-keypad.ch8", 200, &[(100, 0x5)], |chip8| {
+        chip8.bus_mut().write(MENU_CHOICE, 3)
+    });
+}
+
+// The other two keypad tests light up keys while they're held, and keys here
+// are presses rather than held down, so they have nothing to show.
+
+#[test]
+fn beep() {
+    // the note is on screen while the test beeps
+    check("7-beep.ch8", 150, |_| {});
+}
+
+// 8-scrolling.ch8 isn't here: it tests the SUPER-CHIP scroll instructions
+// (00CN, 00FB and 00FC) in high resolution, and this interpreter has neither,
+// so there is no right screen to compare against.
+
+#[test]
+fn bc_test() {
+    check("BC_test.ch8", 60, |_| {});
+}
+
+#[test]
+fn opcode_test() {
+    check("test_opcode.ch8", 60, |_| {});
+}